@@ -3,13 +3,38 @@
 
 use super::CONFIG;
 use crate::application::Application;
-use crate::fixed::{Action, APPNAME, TICK_TIMEOUT, TINY_TIMEOUT};
+use crate::fixed::{
+    Action, APPNAME, INFO_TIMEOUT, TICK_TIMEOUT, TINY_TIMEOUT,
+    TOP_LEVEL_NAME,
+};
+use crate::folder_import;
 use crate::model::TrackID;
 use crate::util;
 use fltk::{app, dialog, prelude::*, tree::TreeItem};
+use std::path::Path;
 
 impl Application {
     pub(crate) fn on_startup(&mut self) {
+        let recovered = self.recover_untitled();
+        if let Some(path) = self.startup_path.take() {
+            if recovered {
+                // The recovered changes must be saved or discarded first
+                if !self.ok_to_clear() {
+                    self.info_view.set_value(&format!(
+                        "Didn't open <font color=navy>{path:?}</font> so as
+                        to keep the recovered changes; save them and then
+                        use <b>File→Open</b>"
+                    ));
+                    return;
+                }
+                self.tlm.clear(); // they were saved or are to be discarded
+            }
+            self.open_startup_path(&path);
+            return;
+        }
+        if recovered {
+            return;
+        }
         let filename = {
             let config = CONFIG.get().read().unwrap();
             config.last_file.clone()
@@ -17,6 +42,37 @@ impl Application {
         if filename.exists() {
             self.load_tlm(&filename);
             self.populate_history_menu_button();
+        } else {
+            self.update_ui();
+        }
+    }
+
+    // Opens a .tlm file, or starts a new file with a list made from a
+    // playlist or folder of tracks
+    fn open_startup_path(&mut self, path: &Path) {
+        if !path.exists() {
+            self.info_view.set_value(&format!(
+                "Failed to open <font color=navy>{path:?}</font>
+                <br><font color=red>no such file or folder</font>"
+            ));
+            self.clear_info_after(INFO_TIMEOUT);
+        } else if path.is_dir() {
+            self.on_file_new();
+            self.new_list_from_folder(
+                TOP_LEVEL_NAME,
+                "",
+                path,
+                folder_import::Options::default(),
+            );
+        } else if path
+            .extension()
+            .is_some_and(|suffix| suffix.eq_ignore_ascii_case("tlm"))
+        {
+            self.load_tlm(path);
+            self.populate_history_menu_button();
+        } else {
+            self.on_file_new();
+            self.new_list_from_playlist(TOP_LEVEL_NAME, "", path);
         }
     }
 
@@ -27,13 +83,20 @@ impl Application {
 
     pub(crate) fn on_tick(&mut self) {
         if self.playing {
-            let pos = self.player.stream_position(self.handle);
-            let length = self.wav.length();
+            let stream_pos = self.player.stream_position(self.handle);
             if self.player.voice_count() == 0 {
                 // Reached the end
                 self.on_track_next();
                 return;
             }
+            if self.current.end > 0.0 && stream_pos >= self.current.end {
+                // Reached the end of a virtual track
+                self.on_track_play_or_pause(); // PAUSE
+                self.on_track_next();
+                return;
+            }
+            let pos = (stream_pos - self.current.start).max(0.0);
+            let length = self.track_length();
             self.time_slider.set_value(pos);
             self.time_label.set_label(&format!(
                 "{}/{}",
@@ -65,16 +128,7 @@ impl Application {
             }
             None => return,
         };
-        let mut treepath = item.label().unwrap_or_default();
-        let mut opt_parent = item.parent();
-        while let Some(parent) = opt_parent {
-            opt_parent = parent.parent();
-            if parent.depth() > 0 {
-                let label = parent.label().unwrap_or_default();
-                treepath.insert(0, '/');
-                treepath.insert_str(0, &label);
-            }
-        }
+        let treepath = util::treepath_for_item(Some(item.clone()));
         self.select_track_in_tree(treepath, item);
         self.on_track_replay();
     }
@@ -93,6 +147,8 @@ impl Application {
                     self.current.treepath = treepath.clone();
                 }
                 self.current.track = track_item.filename.clone();
+                self.current.start = track_item.start;
+                self.current.end = track_item.end;
                 self.load_track();
             }
         }
@@ -118,10 +174,13 @@ impl Application {
                 "&Cancel",
             ) {
                 Some(index) => match index {
-                    0 => true, // don't save and continue
+                    0 => {
+                        self.recovery_writer.remove(&self.tlm.filename);
+                        true // don't save and continue
+                    }
                     1 => {
                         self.on_file_save();
-                        true // save and continue
+                        !self.tlm.is_dirty() // continue only if saved
                     }
                     _ => false, // don't save and don't continue
                 },
@@ -2,10 +2,17 @@
 // License: GPLv3
 
 use super::CONFIG;
-use crate::fixed::{Action, MENU_CHARS, PATH_SEP, TINY_TIMEOUT};
+use crate::duration_scan;
+use crate::fixed::{
+    Action, MENU_CHARS, RECOVERY_TIMEOUT, TINY_TIMEOUT, WATCH_TIMEOUT,
+};
 use crate::html_form;
+use crate::import_worker;
 use crate::main_window;
 use crate::model::{Current, Model};
+use crate::recovery;
+use crate::relink;
+use crate::util;
 use fltk::{
     app,
     app::{channel, App, Receiver, Scheme, Sender},
@@ -19,6 +26,7 @@ use fltk::{
     window::Window,
 };
 use soloud::{audio::Wav, prelude::*, Soloud};
+use std::path::PathBuf;
 
 pub struct Application {
     pub(crate) app: App,
@@ -42,12 +50,17 @@ pub struct Application {
     pub(crate) current: Current,
     pub(crate) tlm: Model,
     pub(crate) find_text: String,
+    pub(crate) startup_path: Option<PathBuf>,
+    pub(crate) duration_scan: Option<duration_scan::Scan>,
+    pub(crate) import: Option<import_worker::Import>,
+    pub(crate) relink_search: Option<relink::Search>,
+    pub(crate) recovery_writer: recovery::Writer,
     pub(crate) sender: Sender<Action>,
     pub(crate) receiver: Receiver<Action>,
 }
 
 impl Application {
-    pub fn new() -> Self {
+    pub fn new(startup_path: Option<PathBuf>) -> Self {
         let app = App::default().with_scheme(Scheme::Gleam);
         let (sender, receiver) = channel::<Action>();
         let mut widgets = main_window::make(sender);
@@ -56,7 +69,7 @@ impl Application {
         let mut player =
             Soloud::default().expect("Cannot access audio backend");
         player.set_pause_all(true);
-        let load = main_window::update_widgets_from_config(&mut widgets);
+        main_window::update_widgets_from_config(&mut widgets);
         let mut volume_slider = widgets.volume_slider.clone();
         let mut time_slider = widgets.time_slider.clone();
         let mut app = Self {
@@ -81,9 +94,19 @@ impl Application {
             current: Current::default(),
             tlm: Model::new(widgets.track_tree),
             find_text: String::new(),
+            startup_path,
+            duration_scan: None,
+            import: None,
+            relink_search: None,
+            recovery_writer: recovery::Writer::default(),
             sender,
             receiver,
         };
+        {
+            let config = CONFIG.get().read().unwrap();
+            app.tlm.track_paths = config.track_paths();
+            app.tlm.backups = config.backups;
+        }
         #[allow(clippy::clone_on_copy)]
         let sender = sender.clone();
         volume_slider.set_callback(move |_| {
@@ -94,15 +117,15 @@ impl Application {
         time_slider.set_callback(move |_| {
             sender.send(Action::TimeUpdate);
         });
-        if load {
-            #[allow(clippy::clone_on_copy)]
-            let sender = sender.clone();
-            app::add_timeout3(TINY_TIMEOUT, move |_| {
-                sender.send(Action::OnStartup);
-            });
-        } else {
-            app.update_ui();
-        }
+        // Always start up since even with nothing to open there may be an
+        // untitled file's changes to recover
+        #[allow(clippy::clone_on_copy)]
+        let sender = sender.clone();
+        app::add_timeout3(TINY_TIMEOUT, move |_| {
+            sender.send(Action::OnStartup);
+        });
+        app.save_recovery_after(RECOVERY_TIMEOUT);
+        app.watch_file_after(WATCH_TIMEOUT);
         app
     }
 
@@ -111,11 +134,15 @@ impl Application {
             if let Some(action) = self.receiver.recv() {
                 match action {
                     Action::ClearInfo => self.info_view.set_value(""),
+                    Action::DurationsScanned => self.on_durations_scanned(),
                     Action::FileNew => self.on_file_new(),
                     Action::FileOpen => self.on_file_open(),
                     Action::FileOpenRecent => self.on_file_open_recent(),
                     Action::FileSave => self.on_file_save(),
                     Action::FileSaveAs => self.on_file_save_as(),
+                    Action::FileRestoreBackup => {
+                        self.on_file_restore_backup()
+                    }
                     Action::FileConfigure => self.on_file_configure(),
                     Action::FileQuit => self.on_file_quit(),
                     Action::EditPromote => self.on_edit_promote(),
@@ -125,17 +152,31 @@ impl Application {
                     Action::EditFind => self.on_edit_find(),
                     Action::EditFindAgain => self.on_edit_find_again(),
                     Action::EditDelete => self.on_edit_delete(),
+                    Action::EditRewritePaths => {
+                        self.on_edit_rewrite_paths()
+                    }
+                    Action::ImportBatch => self.on_import_batch(),
+                    Action::ImportCancel => self.on_import_cancel(),
                     Action::ListAdd => self.on_list_add(),
+                    Action::ListExport => self.on_list_export(),
+                    Action::ListImportFolder => {
+                        self.on_list_import_folder()
+                    }
                     Action::ListRename => self.on_list_rename(),
+                    Action::MissingTracksSearched => {
+                        self.on_missing_tracks_searched()
+                    }
                     Action::HelpAbout => self.on_help_about(),
                     Action::HelpHelp => self.on_help_help(),
                     Action::OnStartup => self.on_startup(),
                     Action::PlayHistoryTrack => {
                         self.on_play_history_track()
                     }
+                    Action::SaveRecovery => self.on_save_recovery(),
                     Action::Tick => self.on_tick(),
                     Action::TimeUpdate => self.on_time_update(),
                     Action::TrackAdd => self.on_track_add(),
+                    Action::TrackCheck => self.on_track_check(),
                     Action::TrackPrevious => self.on_track_previous(),
                     Action::TrackPlayOrPause => {
                         self.on_track_play_or_pause()
@@ -149,11 +190,28 @@ impl Application {
                         self.on_tree_item_double_clicked()
                     }
                     Action::VolumeUpdate => self.on_volume_update(),
+                    Action::WatchFile => self.on_watch_file(),
                 }
             }
         }
     }
 
+    pub fn save_recovery_after(&mut self, secs: f64) {
+        #[allow(clippy::clone_on_copy)]
+        let sender = self.sender.clone();
+        app::add_timeout3(secs, move |_| {
+            sender.send(Action::SaveRecovery);
+        });
+    }
+
+    pub fn watch_file_after(&mut self, secs: f64) {
+        #[allow(clippy::clone_on_copy)]
+        let sender = self.sender.clone();
+        app::add_timeout3(secs, move |_| {
+            sender.send(Action::WatchFile);
+        });
+    }
+
     pub fn clear_info_after(&mut self, secs: f64) {
         #[allow(clippy::clone_on_copy)]
         let sender = self.sender.clone();
@@ -214,10 +272,12 @@ impl Application {
             if i == size {
                 break;
             }
+            // Menu labels use '/' for submenus so '/' and '\\' must be
+            // escaped
             let name = format!(
                 "&{} {}",
                 MENU_CHARS[base + i],
-                treepath.replace(&['\\', '/'][..], PATH_SEP)
+                util::treepath_escaped(&util::treepath_display(treepath))
             );
             self.history_menu_button.add_emit(
                 &name,
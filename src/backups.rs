@@ -0,0 +1,112 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use anyhow::Result;
+use chrono::prelude::*;
+use std::{
+    cmp::Reverse,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Copies the file (if it exists) to a timestamped backup next to it, e.g.,
+// music.tlm.20221031-174512.bak, then deletes all but the newest keep
+// backups. A backup made in the same second as an earlier one has a count
+// after its timestamp, e.g., music.tlm.20221031-174512_2.bak
+pub fn back_up(filename: &Path, keep: usize) -> Result<()> {
+    if keep == 0 || !filename.exists() {
+        return Ok(());
+    }
+    let timestamp = Local::now().format(TIMESTAMP_FORMAT).to_string();
+    let mut backup = backup_for(filename, &timestamp);
+    let mut count = 1;
+    while backup.exists() {
+        count += 1;
+        backup =
+            backup_for(filename, &format!("{timestamp}{COUNT}{count}"));
+    }
+    fs::copy(filename, backup)?;
+    for backup in backups_for(filename).iter().skip(keep) {
+        let _ = fs::remove_file(backup); // Ignore error
+    }
+    Ok(())
+}
+
+// Returns the file's backups newest first
+pub fn backups_for(filename: &Path) -> Vec<PathBuf> {
+    let prefix = prefix_for(filename).to_string_lossy().to_string();
+    let folder = match filename.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    let mut backups: Vec<(PathBuf, (NaiveDateTime, u32))> =
+        fs::read_dir(folder)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter_map(|path| {
+                        let when = when_for(&path, &prefix)?;
+                        Some((path, when))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+    backups.sort_by_key(|(_, when)| Reverse(*when));
+    backups.into_iter().map(|(backup, _)| backup).collect()
+}
+
+// Returns the backup's time for display, e.g., "2022-10-31 17:45:12" (or
+// "2022-10-31 17:45:12 (2)" for a second backup made in that second)
+pub fn timestamp_for(backup: &Path) -> String {
+    let name = backup.file_name().unwrap_or_default().to_string_lossy();
+    name.strip_suffix(SUFFIX)
+        .and_then(|name| name.rsplit_once('.'))
+        .and_then(|(_, timestamp)| parse_timestamp(timestamp))
+        .map(|(when, count)| {
+            let when = when.format("%Y-%m-%d %H:%M:%S");
+            if count > 1 {
+                format!("{when} ({count})")
+            } else {
+                when.to_string()
+            }
+        })
+        .unwrap_or_else(|| name.to_string())
+}
+
+fn prefix_for(filename: &Path) -> OsString {
+    let mut prefix =
+        filename.file_name().unwrap_or_default().to_os_string();
+    prefix.push(".");
+    prefix
+}
+
+fn backup_for(filename: &Path, timestamp: &str) -> PathBuf {
+    let mut name = prefix_for(filename);
+    name.push(timestamp);
+    name.push(SUFFIX);
+    filename.with_file_name(name)
+}
+
+// Returns the backup's time and count if it is one of the file's backups
+fn when_for(path: &Path, prefix: &str) -> Option<(NaiveDateTime, u32)> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.strip_prefix(prefix)
+        .and_then(|name| name.strip_suffix(SUFFIX))
+        .and_then(parse_timestamp)
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<(NaiveDateTime, u32)> {
+    let (when, count) = match timestamp.split_once(COUNT) {
+        Some((when, count)) => (when, count.parse().ok()?),
+        None => (timestamp, 1),
+    };
+    NaiveDateTime::parse_from_str(when, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|when| (when, count))
+}
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const COUNT: char = '_';
+const SUFFIX: &str = ".bak";
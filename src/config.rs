@@ -2,13 +2,15 @@
 // License: GPLv3
 
 use crate::fixed::{
-    APPNAME, MAX_HISTORY_SIZE, MIN_HISTORY_SIZE, SCALE_MAX, SCALE_MIN,
-    WINDOW_HEIGHT_MIN, WINDOW_WIDTH_MIN,
+    APPNAME, DEFAULT_BACKUPS, MAX_BACKUPS, MAX_HISTORY_SIZE,
+    MIN_HISTORY_SIZE, SCALE_MAX, SCALE_MIN, WINDOW_HEIGHT_MIN,
+    WINDOW_WIDTH_MIN,
 };
 use crate::util::{self, PathBufExt};
 use fltk::{app, dialog};
 use ini::Ini;
 use std::{collections::VecDeque, env, path::PathBuf};
+use tlmlib::TrackPaths;
 
 type RecentFiles = VecDeque<PathBuf>;
 
@@ -24,6 +26,11 @@ pub struct Config {
     pub recent_files: RecentFiles,
     pub history_size: usize,
     pub auto_save: bool,
+    pub live_reload: bool,
+    pub backups: usize,
+    pub relative_paths: bool,
+    pub music_roots: Vec<(String, PathBuf)>,
+    pub path_remaps: Vec<(PathBuf, PathBuf)>,
     pub filename: PathBuf,
 }
 
@@ -40,6 +47,14 @@ impl Config {
             if let Some(properties) = ini.section(Some(GENERAL_SECTION)) {
                 read_general_properties(properties, &mut config);
             }
+            if let Some(properties) = ini.section(Some(MUSIC_ROOTS_SECTION))
+            {
+                read_music_roots(properties, &mut config);
+            }
+            if let Some(properties) = ini.section(Some(PATH_REMAPS_SECTION))
+            {
+                read_path_remaps(properties, &mut config);
+            }
         }
         config
     }
@@ -59,8 +74,21 @@ impl Config {
                 .set(VOLUME_KEY, self.volume.to_string())
                 .set(LAST_FILE_KEY, self.last_file.to_string_lossy())
                 .set(HISTORY_SIZE_KEY, self.history_size.to_string())
-                .set(AUTO_SAVE_KEY, self.auto_save.to_string());
+                .set(AUTO_SAVE_KEY, self.auto_save.to_string())
+                .set(LIVE_RELOAD_KEY, self.live_reload.to_string())
+                .set(BACKUPS_KEY, self.backups.to_string())
+                .set(RELATIVE_PATHS_KEY, self.relative_paths.to_string());
             self.save_recent_files(&mut ini);
+            for (name, root) in &self.music_roots {
+                ini.with_section(Some(MUSIC_ROOTS_SECTION))
+                    .set(name, root.to_string_lossy());
+            }
+            for (i, remap) in self.path_remaps.iter().enumerate() {
+                ini.with_section(Some(PATH_REMAPS_SECTION)).set(
+                    format!("{PATH_REMAP_KEY}{}", i + 1),
+                    path_remaps_to_text(std::slice::from_ref(remap)),
+                );
+            }
             match ini.write_to_file(&self.filename) {
                 Ok(_) => {}
                 Err(err) => self.warning(&format!(
@@ -81,6 +109,15 @@ impl Config {
         }
     }
 
+    // How track paths are stored in and read from .tlm files
+    pub fn track_paths(&self) -> TrackPaths {
+        TrackPaths {
+            relative: self.relative_paths,
+            roots: self.music_roots.clone(),
+            remaps: self.path_remaps.clone(),
+        }
+    }
+
     fn warning(&self, message: &str) {
         dialog::message_title(&format!("Warning — {APPNAME}",));
         dialog::message(util::x() - 200, util::y() - 100, message);
@@ -100,6 +137,11 @@ impl Default for Config {
             recent_files: RecentFiles::new(),
             history_size: MAX_HISTORY_SIZE,
             auto_save: true,
+            live_reload: false,
+            backups: DEFAULT_BACKUPS,
+            relative_paths: true,
+            music_roots: vec![],
+            path_remaps: vec![],
             filename: PathBuf::new(),
         }
     }
@@ -191,6 +233,83 @@ fn read_general_properties(
     } else {
         true
     };
+    if let Some(value) = properties.get(LIVE_RELOAD_KEY) {
+        config.live_reload = util::get_bool(value);
+    }
+    if let Some(value) = properties.get(BACKUPS_KEY) {
+        config.backups =
+            util::get_num(value, 0, MAX_BACKUPS, config.backups)
+    }
+    config.relative_paths =
+        if let Some(value) = properties.get(RELATIVE_PATHS_KEY) {
+            util::get_bool(value)
+        } else {
+            true
+        };
+}
+
+fn read_music_roots(properties: &ini::Properties, config: &mut Config) {
+    config.music_roots = properties
+        .iter()
+        .filter(|(name, _)| TrackPaths::is_root_name(name))
+        .map(|(name, root)| (name.to_string(), PathBuf::from(root)))
+        .collect();
+}
+
+// Returns music roots from text with one per line of the form
+// "MUSIC=/home/mark/Music"; entries with invalid names are ignored
+pub fn music_roots_from_text(text: &str) -> Vec<(String, PathBuf)> {
+    text.lines()
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(name, root)| (name.trim(), root.trim()))
+        .filter(|(name, root)| {
+            TrackPaths::is_root_name(name) && !root.is_empty()
+        })
+        .map(|(name, root)| (name.to_string(), PathBuf::from(root)))
+        .collect()
+}
+
+pub fn music_roots_to_text(roots: &[(String, PathBuf)]) -> String {
+    roots
+        .iter()
+        .map(|(name, root)| format!("{name}={}", root.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn read_path_remaps(properties: &ini::Properties, config: &mut Config) {
+    config.path_remaps = properties
+        .iter()
+        .filter(|(key, _)| key.starts_with(PATH_REMAP_KEY))
+        .flat_map(|(_, value)| path_remaps_from_text(value))
+        .collect();
+}
+
+// Returns (shared, local) path remaps from text with one per line of the
+// form "/home/a/Music=/mnt/nas/music"
+pub fn path_remaps_from_text(text: &str) -> Vec<(PathBuf, PathBuf)> {
+    text.lines()
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(shared, local)| (shared.trim(), local.trim()))
+        .filter(|(shared, local)| !shared.is_empty() && !local.is_empty())
+        .map(|(shared, local)| {
+            (PathBuf::from(shared), PathBuf::from(local))
+        })
+        .collect()
+}
+
+pub fn path_remaps_to_text(remaps: &[(PathBuf, PathBuf)]) -> String {
+    remaps
+        .iter()
+        .map(|(shared, local)| {
+            format!(
+                "{}={}",
+                shared.to_string_lossy(),
+                local.to_string_lossy()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 static WINDOW_SECTION: &str = "Window";
@@ -205,3 +324,9 @@ static LAST_FILE_KEY: &str = "lastfile";
 static RECENT_FILE_KEY: &str = "recentfile";
 static HISTORY_SIZE_KEY: &str = "historysize";
 static AUTO_SAVE_KEY: &str = "autosave";
+static LIVE_RELOAD_KEY: &str = "livereload";
+static BACKUPS_KEY: &str = "backups";
+static RELATIVE_PATHS_KEY: &str = "relativepaths";
+static MUSIC_ROOTS_SECTION: &str = "MusicRoots";
+static PATH_REMAPS_SECTION: &str = "PathRemaps";
+static PATH_REMAP_KEY: &str = "remap";
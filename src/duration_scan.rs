@@ -0,0 +1,131 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::fixed::Action;
+use crate::model::TrackID;
+use crate::util;
+use fltk::app::Sender;
+use std::{
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+// A track whose duration may need to be read; start is non-zero for a
+// virtual track that plays to the end of its file
+pub struct Job {
+    pub tid: TrackID,
+    pub filename: PathBuf,
+    pub secs: f64,
+    pub start: f64,
+}
+
+// Reads durations in a worker thread for tracks whose secs are 0 or whose
+// files have been modified since the .tlm file was saved; each new
+// duration is sent as a (tid, secs) pair and the GUI is told with
+// Action::DurationsScanned. More jobs can be added while it is running
+pub struct Scan {
+    pub receiver: mpsc::Receiver<(TrackID, f64)>,
+    queue: Arc<Mutex<Queue>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+// The jobs still to do; running is cleared (with the lock held) when the
+// worker finds no more jobs so that no job can be added after it's done
+struct Queue {
+    jobs: VecDeque<Job>,
+    running: bool,
+}
+
+impl Scan {
+    pub fn new(
+        jobs: Vec<Job>,
+        saved: Option<SystemTime>,
+        sender: Sender<Action>,
+    ) -> Self {
+        let (results, receiver) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(Queue {
+            jobs: jobs.into(),
+            running: true,
+        }));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let queue = Arc::clone(&queue);
+            let cancelled = Arc::clone(&cancelled);
+            move || {
+                let mut count = 0;
+                loop {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let job = {
+                        let mut queue = queue.lock().unwrap();
+                        match queue.jobs.pop_front() {
+                            Some(job) => job,
+                            None => {
+                                queue.running = false;
+                                break;
+                            }
+                        }
+                    };
+                    if !needs_scan(&job, saved) {
+                        continue;
+                    }
+                    if let Ok(secs) = util::get_track_secs(&job.filename) {
+                        let secs = (secs - job.start).max(0.0);
+                        if util::same_secs(secs, job.secs) {
+                            continue;
+                        }
+                        if results.send((job.tid, secs)).is_err() {
+                            return; // the Scan has been dropped
+                        }
+                        count += 1;
+                        if count % BATCH_SIZE == 0 {
+                            sender.send(Action::DurationsScanned);
+                        }
+                    }
+                }
+                if count % BATCH_SIZE != 0 {
+                    sender.send(Action::DurationsScanned);
+                }
+            }
+        });
+        Self { receiver, queue, cancelled }
+    }
+
+    // Adds the jobs to those still to do, or gives them back if the scan
+    // has finished
+    pub fn add(&self, jobs: Vec<Job>) -> Result<(), Vec<Job>> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.running {
+            queue.jobs.extend(jobs);
+            Ok(())
+        } else {
+            Err(jobs)
+        }
+    }
+}
+
+impl Drop for Scan {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn needs_scan(job: &Job, saved: Option<SystemTime>) -> bool {
+    if job.secs <= 0.0 {
+        return true;
+    }
+    match (saved, fs::metadata(&job.filename).and_then(|m| m.modified())) {
+        (Some(saved), Ok(modified)) => modified > saved, // stale
+        _ => false,
+    }
+}
+
+const BATCH_SIZE: usize = 25;
@@ -2,13 +2,20 @@
 // License: GPLv3
 
 use crate::application::Application;
-use crate::fixed::{APPNAME, DELETED_NAME, TINY_TIMEOUT, TOP_LEVEL_NAME};
+use crate::fixed::{
+    APPNAME, DELETED_NAME, INFO_TIMEOUT, TINY_TIMEOUT, TOP_LEVEL_NAME,
+};
 use crate::model::TrackID;
+use crate::util;
 use fltk::{app, dialog, prelude::*, tree::TreeItem};
+use std::path::PathBuf;
 
 impl Application {
     pub(crate) fn on_edit_move_up(&mut self) {
         if let Some(mut item) = self.tlm.track_tree.first_selected_item() {
+            if self.list_locked_by_import(&item) {
+                return;
+            }
             if let Some(prev) = item.prev_sibling() {
                 if item.move_above(&prev).is_ok() {
                     self.tlm.set_dirty();
@@ -20,6 +27,9 @@ impl Application {
 
     pub(crate) fn on_edit_move_down(&mut self) {
         if let Some(mut item) = self.tlm.track_tree.first_selected_item() {
+            if self.list_locked_by_import(&item) {
+                return;
+            }
             if let Some(next) = item.next_sibling() {
                 if item.move_below(&next).is_ok() {
                     self.tlm.set_dirty();
@@ -36,6 +46,9 @@ impl Application {
     */
     pub(crate) fn on_edit_promote(&mut self) {
         if let Some(mut item) = self.tlm.track_tree.first_selected_item() {
+            if self.list_locked_by_import(&item) {
+                return;
+            }
             if let Some(parent) = item.parent() {
                 if parent.is_root() {
                     return; // can't promote beyond the root
@@ -57,6 +70,9 @@ impl Application {
     */
     pub(crate) fn on_edit_demote(&mut self) {
         if let Some(mut item) = self.tlm.track_tree.first_selected_item() {
+            if self.list_locked_by_import(&item) {
+                return;
+            }
             if let Some(prev) = item.prev_sibling() {
                 let tid = unsafe { prev.user_data::<TrackID>() };
                 if tid.is_none() {
@@ -117,23 +133,18 @@ impl Application {
     }
 
     fn select_item(&mut self, item: TreeItem) {
-        let mut treepath = String::new();
-        if let Some(name) = item.label() {
-            treepath.insert_str(0, &name);
-        }
         let mut opt_parent = item.parent();
         while let Some(mut parent) = opt_parent {
             parent.open();
             if parent.depth() < 1 {
                 break;
             }
-            if let Some(name) = parent.label() {
-                treepath.insert(0, '/');
-                treepath.insert_str(0, &name);
-            }
             opt_parent = parent.parent();
         }
-        let _ = self.tlm.track_tree.select(&treepath, false);
+        // Names are escaped so that those containing / or \ are found
+        let treepath = util::treepath_for_item(Some(item.clone()));
+        let treepath = treepath.strip_suffix('/').unwrap_or(&treepath);
+        let _ = self.tlm.track_tree.select(treepath, false);
         let mut tree = self.tlm.track_tree.clone();
         app::add_timeout3(TINY_TIMEOUT, move |_| {
             tree.show_item_middle(&item);
@@ -142,6 +153,9 @@ impl Application {
 
     pub(crate) fn on_edit_delete(&mut self) {
         if let Some(mut item) = self.tlm.track_tree.first_selected_item() {
+            if self.list_locked_by_import(&item) {
+                return;
+            }
             if let Some(name) = item.label() {
                 if item.depth() == 1 && name == DELETED_NAME {
                     self.maybe_delete(&mut item);
@@ -217,4 +231,35 @@ impl Application {
             }
         }
     }
+
+    pub(crate) fn on_edit_rewrite_paths(&mut self) {
+        let folder = util::get_track_dir(&self.current.track)
+            .to_string_lossy()
+            .to_string();
+        dialog::message_title(&format!("Rewrite Track Paths — {APPNAME}"));
+        let from = match dialog::input_default("Replace folder", &folder) {
+            Some(from) if !from.trim().is_empty() => from,
+            _ => return,
+        };
+        dialog::message_title(&format!("Rewrite Track Paths — {APPNAME}"));
+        let to = match dialog::input_default("With folder", &from) {
+            Some(to) if !to.trim().is_empty() => to,
+            _ => return,
+        };
+        let (from, to) =
+            (PathBuf::from(from.trim()), PathBuf::from(to.trim()));
+        let count = self.tlm.rewrite_paths(&from, &to);
+        if let Some(track) =
+            util::rewritten_path(&self.current.track, &from, &to)
+        {
+            self.current.track = track;
+        }
+        self.update_ui();
+        self.scan_durations();
+        self.info_view.set_value(&format!(
+            "Rewrote {count} track paths from <font color=navy>{from:?}</font> \
+            to <font color=navy>{to:?}</font>"
+        ));
+        self.clear_info_after(INFO_TIMEOUT);
+    }
 }
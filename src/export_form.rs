@@ -0,0 +1,184 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::fixed::{APPNAME, BUTTON_HEIGHT, BUTTON_WIDTH, ICON, PAD};
+use crate::util::{self, PathBufExt};
+use fltk::{
+    app,
+    button::{Button, CheckButton, RadioRoundButton},
+    dialog::{FileDialog, FileDialogOptions, FileDialogType},
+    enums::{Align, FrameType},
+    frame::Frame,
+    group::Flex,
+    image::SvgImage,
+    prelude::*,
+    window::Window,
+};
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+pub struct Form {
+    form: Window,
+    pub ok: Rc<RefCell<bool>>,
+    pub include_sublists: Rc<RefCell<bool>>,
+    pub relative: Rc<RefCell<bool>>,
+}
+
+impl Form {
+    pub fn new(name: &str) -> Self {
+        let ok = Rc::from(RefCell::from(false));
+        let include_sublists = Rc::from(RefCell::from(true));
+        let relative = Rc::from(RefCell::from(false));
+        let mut form = make_form();
+        let mut vbox = Flex::default().size_of_parent().column();
+        vbox.set_margin(PAD);
+        vbox.set_pad(PAD);
+        make_name_row(name);
+        let mut widgets = make_widgets();
+        let (button_row, mut buttons) = make_buttons();
+        vbox.set_size(&button_row, BUTTON_HEIGHT);
+        vbox.end();
+        form.end();
+        form.make_modal(true);
+        add_event_handlers(
+            &mut form,
+            &widgets,
+            &mut buttons,
+            Rc::clone(&ok),
+            Rc::clone(&include_sublists),
+            Rc::clone(&relative),
+        );
+        widgets.include_sublists_checkbox.take_focus().unwrap();
+        form.show();
+        while form.shown() {
+            app::wait();
+        }
+        Self { form, ok, include_sublists, relative }
+    }
+}
+
+impl Drop for Form {
+    fn drop(&mut self) {
+        app::delete_widget(self.form.clone());
+    }
+}
+
+struct Widgets {
+    pub include_sublists_checkbox: CheckButton,
+    pub relative_radio: RadioRoundButton,
+}
+
+struct Buttons {
+    pub ok_button: Button,
+    pub cancel_button: Button,
+}
+
+fn make_form() -> Window {
+    let image = SvgImage::from_data(ICON).unwrap();
+    let mut form = Window::default()
+        .with_size(WIDTH, HEIGHT)
+        .with_label(&format!("Export List — {APPNAME}"));
+    if let Some(window) = app::first_window() {
+        form.set_pos(window.x() + 50, window.y() + 100);
+    }
+    form.set_icon(Some(image));
+    form
+}
+
+fn make_name_row(name: &str) {
+    let mut row = Flex::default().row();
+    let label = Frame::default()
+        .with_label("List")
+        .with_align(Align::Inside | Align::Left);
+    let mut name_label = Frame::default()
+        .with_label(name)
+        .with_align(Align::Inside | Align::Left);
+    name_label.set_frame(FrameType::EngravedFrame);
+    row.set_size(&label, WIDTH / 6);
+    row.end();
+}
+
+fn make_widgets() -> Widgets {
+    let mut row = Flex::default().row();
+    row.set_pad(PAD);
+    let include_sublists_checkbox =
+        CheckButton::default().with_label("Include &Sublists");
+    include_sublists_checkbox.set_checked(true);
+    row.end();
+    let mut row = Flex::default().row();
+    row.set_pad(PAD);
+    let mut absolute_radio =
+        RadioRoundButton::default().with_label("&Absolute Paths");
+    absolute_radio.toggle(true);
+    let relative_radio =
+        RadioRoundButton::default().with_label("&Relative Paths");
+    row.end();
+    Widgets { include_sublists_checkbox, relative_radio }
+}
+
+fn make_buttons() -> (Flex, Buttons) {
+    let mut row = Flex::default().size_of_parent().row();
+    row.set_pad(PAD);
+    Frame::default(); // pad left of buttons
+    let ok_button = Button::default().with_label("&OK");
+    let cancel_button = Button::default().with_label("&Cancel");
+    Frame::default(); // pad right of buttons
+    row.set_size(&ok_button, BUTTON_WIDTH);
+    row.set_size(&cancel_button, BUTTON_WIDTH);
+    row.end();
+    (row, Buttons { ok_button, cancel_button })
+}
+
+fn add_event_handlers(
+    form: &mut Window,
+    widgets: &Widgets,
+    buttons: &mut Buttons,
+    ok: Rc<RefCell<bool>>,
+    include_sublists: Rc<RefCell<bool>>,
+    relative: Rc<RefCell<bool>>,
+) {
+    buttons.ok_button.set_callback({
+        let include_sublists_checkbox =
+            widgets.include_sublists_checkbox.clone();
+        let relative_radio = widgets.relative_radio.clone();
+        let mut form = form.clone();
+        move |_| {
+            *ok.borrow_mut() = true;
+            *include_sublists.borrow_mut() =
+                include_sublists_checkbox.is_checked();
+            *relative.borrow_mut() = relative_radio.is_toggled();
+            form.hide();
+        }
+    });
+    buttons.cancel_button.set_callback({
+        let mut form = form.clone();
+        move |_| {
+            form.hide();
+        }
+    });
+}
+
+// Returns the playlist filename to export to or an empty path if the user
+// cancelled
+pub fn playlist_filename(name: &str) -> PathBuf {
+    let mut form = FileDialog::new(FileDialogType::BrowseSaveFile);
+    form.set_title(&format!("Export List — {APPNAME}"));
+    let _ = form.set_directory(&util::get_tlm_dir()); // Ignore error
+    form.set_filter(
+        "M3U8 Playlists\t*.m3u8\n\
+        PLS Playlists\t*.pls\n\
+        XSPF Playlists\t*.xspf",
+    );
+    // A list name may contain path separators, e.g., AC/DC → AC-DC.m3u8
+    let name = name.replace(['/', '\\'], "-");
+    form.set_preset_file(&format!("{name}.m3u8"));
+    form.set_option(FileDialogOptions::SaveAsConfirm);
+    form.show();
+    let mut filename = form.filename();
+    if !filename.is_empty() && filename.extension().is_none() {
+        filename.set_extension("m3u8");
+    }
+    filename
+}
+
+const WIDTH: i32 = 340;
+const HEIGHT: i32 = PAD + ((BUTTON_HEIGHT + PAD) * 4);
@@ -3,16 +3,29 @@
 
 use super::CONFIG;
 use crate::application::Application;
-use crate::fixed::{APPNAME, INFO_TIMEOUT, MAX_RECENT_FILES};
+use crate::backups;
+use crate::fixed::{
+    APPNAME, INFO_TIMEOUT, MAX_RECENT_FILES, RECOVERY_TIMEOUT,
+    WATCH_TIMEOUT,
+};
+use crate::html_form;
 use crate::list_form::{self, Reply};
+use crate::model::TrackID;
 use crate::options_form;
+use crate::recovery;
 use crate::util::{self, PathBufExt};
 use fltk::{
     app,
-    dialog::{FileDialog, FileDialogOptions, FileDialogType},
+    dialog::{self, FileDialog, FileDialogOptions, FileDialogType},
     prelude::*,
 };
-use std::path::{Path, PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tlmlib::{
+    Damaged, Repair, FORMAT_VERSION, INVALID_TID, PORTABLE_PATHS_VERSION,
+};
 
 impl Application {
     pub(crate) fn on_file_new(&mut self) {
@@ -20,6 +33,9 @@ impl Application {
             return;
         }
         self.tlm.clear();
+        self.duration_scan = None;
+        self.import = None;
+        self.relink_search = None;
         self.tlm.filename = PathBuf::new();
         self.clear_title();
         self.info_view.set_value(
@@ -93,17 +109,45 @@ impl Application {
         if !self.ok_to_clear() {
             return;
         }
-        match self.tlm.load(filename) {
+        self.import = None; // its list is about to go
+        self.relink_search = None;
+        let mut repairs = vec![];
+        let mut loaded = self.tlm.load(filename);
+        if let Err(err) = &loaded {
+            // Only a damaged file can be (partly) recovered, not, e.g., a
+            // missing or unreadable one
+            if err.is::<Damaged>()
+                && want_to_recover(filename, &err.to_string())
+            {
+                loaded = self
+                    .tlm
+                    .load_lenient(filename)
+                    .map(|fixed| repairs = fixed);
+            }
+        }
+        match loaded {
             Ok(_) => {
                 self.update_title(filename);
                 self.update_recent_files(filename);
                 self.close_children();
+                let recovered = if self.maybe_recover() {
+                    "<br><font color=green>Recovered unsaved changes; save
+                    to keep them.</font>"
+                } else {
+                    ""
+                };
                 self.info_view.set_value(&format!(
-                    "Opened <font color=navy>{filename:?}</font>"
+                    "Opened <font color=navy>{filename:?}</font>{}{recovered}",
+                    self.version_note()
                 ));
                 self.select_recent_track();
+                self.scan_durations();
+                if !repairs.is_empty() {
+                    show_repairs(filename, &repairs);
+                }
             }
             Err(err) => {
+                self.duration_scan = None;
                 self.clear_title();
                 self.info_view.set_value(&format!(
                     "Failed to open <font color=navy>{filename:?}</font>
@@ -116,6 +160,152 @@ impl Application {
         app::redraw(); // redraws the world
     }
 
+    // Offers to recover an untitled file's unsaved changes left by a crash
+    pub(crate) fn recover_untitled(&mut self) -> bool {
+        if !self.maybe_recover() {
+            return false;
+        }
+        self.info_view.set_value(
+            "<font color=green>Recovered unsaved changes; save to keep
+            them.</font>",
+        );
+        self.select_recent_track();
+        self.scan_durations();
+        self.update_ui();
+        app::redraw(); // redraws the world
+        true
+    }
+
+    // Offers to restore the changes that a crash left in the current
+    // file's recovery file; returns true if they were
+    fn maybe_recover(&mut self) -> bool {
+        let recovery = match recovery::find(&self.tlm.filename) {
+            Some(recovery) => recovery,
+            None => return false,
+        };
+        let filename = self.tlm.filename.clone();
+        let name = if filename.is_empty() {
+            "an untitled file".to_string()
+        } else {
+            format!("{filename:?}")
+        };
+        dialog::message_title(&format!("Recover — {APPNAME}"));
+        match dialog::choice2_default(
+            &format!(
+                "{APPNAME} closed without saving the changes to \
+                {name}.\n\nRecover them?"
+            ),
+            "&Discard",
+            "&Recover",
+            "",
+        ) {
+            Some(0) => self.recovery_writer.remove(&filename),
+            Some(1) => match self.tlm.restore(&recovery) {
+                Ok(_) => return true,
+                Err(err) => {
+                    util::popup_error_message(&format!(
+                        "Failed to recover: {err}"
+                    ));
+                    if !filename.is_empty() {
+                        let _ = self.tlm.load(&filename); // Ignore error
+                    }
+                }
+            },
+            _ => (), // keep the recovery file for next time
+        }
+        false
+    }
+
+    // Writes the recovery file while there are unsaved changes so that
+    // they can be recovered after a crash
+    pub(crate) fn on_save_recovery(&mut self) {
+        let generation = self.tlm.generation();
+        if self.tlm.is_dirty() && self.recovery_writer.is_due(generation) {
+            self.recovery_writer.write(self.tlm.to_tlm(), generation);
+        }
+        self.save_recovery_after(RECOVERY_TIMEOUT);
+    }
+
+    // Reloads the file if it has changed on disk (e.g., edited on another
+    // machine and synced) providing live reload is on, there are no
+    // unsaved changes, and the new file can be read (i.e., it isn't
+    // half-written)
+    pub(crate) fn on_watch_file(&mut self) {
+        let live_reload = CONFIG.get().read().unwrap().live_reload;
+        if live_reload
+            && !self.tlm.is_dirty()
+            && !self.tlm.filename.is_empty()
+            && self.import.is_none()
+            && self.tlm.changed_on_disk()
+            && self.tlm.reload().is_ok()
+        {
+            self.refind_current();
+            self.scan_durations();
+            self.update_ui();
+            self.info_view.set_value(&format!(
+                "Reloaded <font color=navy>{:?}</font> since it changed on
+                disk",
+                self.tlm.filename
+            ));
+            self.clear_info_after(INFO_TIMEOUT);
+        }
+        self.watch_file_after(WATCH_TIMEOUT);
+    }
+
+    // After a reload the current track (which may be playing) is found by
+    // its tree path, or failing that by its file; if it's gone it carries
+    // on playing but no longer has a track ID
+    fn refind_current(&mut self) {
+        let tid = self
+            .tlm
+            .track_tree
+            .find_item(&self.current.treepath)
+            .and_then(|item| unsafe { item.user_data::<TrackID>() })
+            .filter(|tid| self.is_current_track(*tid))
+            .or_else(|| {
+                self.tlm
+                    .track_for_tid
+                    .keys()
+                    .copied()
+                    .find(|tid| self.is_current_track(*tid))
+            });
+        self.current.tid = tid.unwrap_or(INVALID_TID);
+    }
+
+    fn is_current_track(&self, tid: TrackID) -> bool {
+        self.tlm.track_for_tid.get(&tid).is_some_and(|track| {
+            track.filename == self.current.track
+                && track.start == self.current.start
+        })
+    }
+
+    // Newer files are read best-effort and older ones are upgraded (and
+    // saved in the current format)
+    fn version_note(&self) -> String {
+        let version = self.tlm.version;
+        if version > FORMAT_VERSION {
+            format!(
+                "<br><font color=red>This file is from a newer version of
+                {APPNAME} (format {version}) so anything this version
+                doesn't understand was skipped. Use <b>File→Save As</b> to
+                save a copy.</font>"
+            )
+        } else if version < PORTABLE_PATHS_VERSION {
+            format!(
+                "<br><font color=green>Upgraded from format {version} to
+                {FORMAT_VERSION}. Track paths that aren't absolute are now
+                relative to this file's folder.</font>"
+            )
+        } else if version < FORMAT_VERSION {
+            format!(
+                "<br><font color=green>Upgraded from format {version} to
+                {FORMAT_VERSION}.</font>"
+            )
+        } else {
+            String::new()
+        }
+    }
+
     fn select_recent_track(&mut self) {
         if let Some(treepath) = self.tlm.history_front() {
             let treepath = treepath.clone();
@@ -126,7 +316,9 @@ impl Application {
             let mut treepath = String::new();
             let mut opt_item = self.tlm.track_tree.first();
             while let Some(item) = opt_item {
-                treepath.push_str(&item.label().unwrap_or_default());
+                treepath.push_str(&util::treepath_escaped(
+                    &item.label().unwrap_or_default(),
+                ));
                 if item.has_children() {
                     treepath.push('/');
                     opt_item = item.next();
@@ -173,8 +365,62 @@ impl Application {
     pub(crate) fn on_file_save(&mut self) {
         if self.tlm.filename.is_empty() {
             self.on_file_save_as();
-        } else if let Err(err) = self.tlm.save() {
-            util::popup_error_message(&format!("Failed to save: {err}"));
+        } else if self.ok_to_overwrite() {
+            match self.tlm.save() {
+                Ok(()) => self.recovery_writer.remove(&self.tlm.filename),
+                Err(err) => util::popup_error_message(&format!(
+                    "Failed to save: {err}"
+                )),
+            }
+        }
+    }
+
+    // If the file has changed on disk since it was loaded or saved (e.g.,
+    // by someone else) offers to merge in its additions, reload it (losing
+    // the unsaved changes), or overwrite it; returns true if it is still ok
+    // to save. Merge is first since it is the choice that loses nothing
+    fn ok_to_overwrite(&mut self) -> bool {
+        if !self.tlm.changed_on_disk() {
+            return true;
+        }
+        let filename = self.tlm.filename.clone();
+        dialog::message_title(&format!("Changed on Disk — {APPNAME}"));
+        match dialog::choice2_default(
+            &format!(
+                "{filename:?} has changed on disk since it was opened or \
+                saved.\n\nMerge in its additions and then save, reload it \
+                (losing your unsaved changes), or overwrite it?"
+            ),
+            "&Merge",
+            "&Reload",
+            "&Overwrite",
+        ) {
+            Some(0) => match self.tlm.merge_from_disk() {
+                Ok(added) => {
+                    self.info_view.set_value(&format!(
+                        "Merged {added} lists and tracks from <font \
+                        color=navy>{filename:?}</font> before saving"
+                    ));
+                    self.clear_info_after(INFO_TIMEOUT);
+                    self.update_ui();
+                    app::redraw(); // redraws the world
+                    true
+                }
+                Err(err) => {
+                    util::popup_error_message(&format!(
+                        "Failed to merge: {err}"
+                    ));
+                    false
+                }
+            },
+            Some(1) => {
+                self.tlm.clear(); // discard the unsaved changes
+                self.recovery_writer.remove(&filename);
+                self.load_tlm(&filename);
+                false
+            }
+            Some(2) => true,
+            _ => false, // cancelled
         }
     }
 
@@ -191,8 +437,11 @@ impl Application {
         form.show();
         let filename = form.filename();
         if !filename.is_empty() {
+            let old_filename = self.tlm.filename.clone();
             match self.tlm.save_as(&filename) {
                 Ok(()) => {
+                    self.recovery_writer.remove(&old_filename);
+                    self.recovery_writer.remove(&filename);
                     self.update_title(&filename);
                     self.update_recent_files(&filename);
                 }
@@ -203,6 +452,78 @@ impl Application {
         }
     }
 
+    pub(crate) fn on_file_restore_backup(&mut self) {
+        let filenames = if self.tlm.filename.is_empty() {
+            vec![]
+        } else {
+            backups::backups_for(&self.tlm.filename)
+        };
+        if filenames.is_empty() {
+            util::popup_error_message("There are no backups of this file");
+            return;
+        }
+        let list: Vec<String> = filenames
+            .iter()
+            .map(|backup| backups::timestamp_for(backup))
+            .collect();
+        let form = list_form::Form::new(
+            "Restore Backup",
+            "&Restore",
+            "&Delete",
+            &list[..],
+        );
+        let reply = *form.reply.borrow();
+        match reply {
+            Reply::Select(index) => {
+                if !self.ok_to_clear() {
+                    return;
+                }
+                // Saving may have deleted it to keep the number of backups
+                if filenames[index].exists() {
+                    self.restore_backup(&filenames[index], &list[index]);
+                } else {
+                    util::popup_error_message(
+                        "The backup was deleted when the file was saved",
+                    );
+                }
+            }
+            Reply::Delete(index) => {
+                let _ = fs::remove_file(&filenames[index]); // Ignore error
+            }
+            Reply::DeleteAll => {
+                for backup in &filenames {
+                    let _ = fs::remove_file(backup); // Ignore error
+                }
+            }
+            Reply::Cancel => (),
+        }
+    }
+
+    fn restore_backup(&mut self, backup: &Path, timestamp: &str) {
+        self.import = None; // its list is about to go
+        self.relink_search = None;
+        match self.tlm.restore(backup) {
+            Ok(_) => {
+                self.close_children();
+                self.info_view.set_value(&format!(
+                    "Restored the backup from {timestamp}; save to keep it"
+                ));
+                self.select_recent_track();
+                self.scan_durations();
+            }
+            Err(err) => {
+                self.duration_scan = None;
+                self.info_view.set_value(&format!(
+                    "Failed to restore the backup from {timestamp}
+                    <br><font color=red>{err}</font>"
+                ));
+                self.clear_info_after(INFO_TIMEOUT);
+            }
+        }
+        self.update_ui();
+        app::redraw(); // redraws the world
+    }
+
     pub(crate) fn on_file_configure(&mut self) {
         let old_size = {
             let config = CONFIG.get().read().unwrap();
@@ -218,6 +539,10 @@ impl Application {
             if old_size != new_size {
                 self.populate_history_menu_button();
             }
+            // Used from the next save or load
+            let config = CONFIG.get().read().unwrap();
+            self.tlm.track_paths = config.track_paths();
+            self.tlm.backups = config.backups;
         }
     }
 
@@ -235,10 +560,51 @@ impl Application {
         if self.tlm.is_dirty() {
             if auto_save {
                 self.on_file_save();
-            } else if !self.ok_to_clear() {
+            }
+            // If it wasn't saved (e.g., it is from a newer version) ask
+            if self.tlm.is_dirty() && !self.ok_to_clear() {
                 return;
             }
         }
         self.app.quit();
     }
 }
+
+fn want_to_recover(filename: &Path, err: &str) -> bool {
+    dialog::message_title(&format!("Open — {APPNAME}"));
+    dialog::choice2_default(
+        &format!(
+            "Failed to open {filename:?}:\n{err}\n\nOpen as much of it as \
+            can be read?"
+        ),
+        "&Cancel",
+        "&Recover",
+        "",
+    ) == Some(1)
+}
+
+// Lists the lines a lenient load skipped or repaired
+fn show_repairs(filename: &Path, repairs: &[Repair]) {
+    let mut html = format!(
+        "<p>Recovered <font color=navy>{}</font> but had to skip or repair
+        {} lines; save it to keep the repairs:</p><table>",
+        util::html_escaped(&filename.to_string_lossy()),
+        repairs.len()
+    );
+    for repair in repairs {
+        html.push_str(&format!(
+            "<tr><td valign=top align=right>{}</td><td>{}",
+            repair.lino,
+            util::html_escaped(&repair.what)
+        ));
+        if !repair.line.is_empty() {
+            html.push_str(&format!(
+                "<br><tt><font color=gray>{}</font></tt>",
+                util::html_escaped(&repair.line.escape_debug().to_string())
+            ));
+        }
+        html.push_str("</td></tr>");
+    }
+    html.push_str("</table>");
+    html_form::Form::new("Recovered", &html, true, 600, 400, true);
+}
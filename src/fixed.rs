@@ -41,9 +41,13 @@ pub const VOLUME_ICON: &str =
     include_str!("../images/audio-volume-high.svg");
 pub const TIME_ICON: &str = include_str!("../images/time.svg");
 pub const PATH_SEP: &str = "→";
+pub const AUDIO_SUFFIXES: [&str; 6] =
+    ["flac", "mogg", "mp3", "oga", "ogg", "wav"];
 pub const MAX_RECENT_FILES: usize = 9;
 pub const MIN_HISTORY_SIZE: usize = 2;
 pub const MAX_HISTORY_SIZE: usize = 35;
+pub const DEFAULT_BACKUPS: usize = 3;
+pub const MAX_BACKUPS: usize = 20;
 pub const PAD: i32 = 6;
 pub const WINDOW_WIDTH_MIN: i32 = 590;
 pub const WINDOW_HEIGHT_MIN: i32 = 380;
@@ -57,6 +61,8 @@ pub const SCALE_MAX: f32 = 3.5;
 pub const TINY_TIMEOUT: f64 = 0.1;
 pub const TICK_TIMEOUT: f64 = 0.1;
 pub const INFO_TIMEOUT: f64 = 10.0;
+pub const RECOVERY_TIMEOUT: f64 = 30.0;
+pub const WATCH_TIMEOUT: f64 = 2.0;
 pub static MENU_CHARS: [char; 35] = [
     '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E',
     'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
@@ -95,6 +101,7 @@ pub fn initialize_time_icons() {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Action {
     ClearInfo,
+    DurationsScanned,
     EditDelete,
     EditDemote,
     EditFind,
@@ -102,22 +109,31 @@ pub enum Action {
     EditMoveDown,
     EditMoveUp,
     EditPromote,
+    EditRewritePaths,
     FileConfigure,
     FileNew,
     FileOpen,
     FileOpenRecent,
     FileQuit,
+    FileRestoreBackup,
     FileSave,
     FileSaveAs,
     HelpAbout,
     HelpHelp,
+    ImportBatch,
+    ImportCancel,
     ListAdd,
+    ListExport,
+    ListImportFolder,
     ListRename,
+    MissingTracksSearched,
     OnStartup,
     PlayHistoryTrack,
+    SaveRecovery,
     Tick,
     TimeUpdate,
     TrackAdd,
+    TrackCheck,
     TrackHistory,
     TrackLouder,
     TrackNext,
@@ -127,6 +143,7 @@ pub enum Action {
     TrackReplay,
     TreeItemDoubleClicked,
     VolumeUpdate,
+    WatchFile,
 }
 
 pub fn about_html(player: &Soloud) -> String {
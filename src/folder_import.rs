@@ -0,0 +1,252 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::util;
+use anyhow::Result;
+use glob::{MatchOptions, Pattern};
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+};
+use walkdir::{DirEntry, WalkDir};
+
+// How to add a folder's tracks as a new list
+pub struct Options {
+    pub include_subdirs: bool,
+    pub nested_subdirs: bool,
+    pub order: Order,
+    pub filter: Filter,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            include_subdirs: true,
+            nested_subdirs: false,
+            order: Order::FileName,
+            filter: Filter::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    FileName,
+    Natural, // e.g., "2 Song" before "10 Song"
+    Tags,    // by disc then track number, then naturally
+}
+
+// Calls add() with each of the folder's wanted tracks in order, along
+// with the names of the sublists it belongs in (which are only non-empty
+// for nested subfolders); returns false if add() does, i.e., to cancel
+pub fn read_folder(
+    folder: &Path,
+    options: &mut Options,
+    add: &mut dyn FnMut(&[String], PathBuf) -> bool,
+) -> bool {
+    read_subfolder(folder, options, &mut vec![], add)
+}
+
+fn read_subfolder(
+    folder: &Path,
+    options: &mut Options,
+    lists: &mut Vec<String>,
+    add: &mut dyn FnMut(&[String], PathBuf) -> bool,
+) -> bool {
+    let mut entries = sorted_entries(folder, options.order);
+    if options.nested_subdirs {
+        // Tracks first, then sublists
+        entries.sort_by_key(|entry| entry.file_type().is_dir());
+    }
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type().is_dir() {
+            if is_hidden(&entry) {
+                continue;
+            }
+            if options.nested_subdirs {
+                // Sublists are only made for tracks, so empty and
+                // non-audio folders are skipped
+                lists.push(util::canonicalize(path));
+                let ok = read_subfolder(path, options, lists, add);
+                lists.pop();
+                if !ok {
+                    return false;
+                }
+            } else if options.include_subdirs
+                && !read_subfolder(path, options, lists, add)
+            {
+                return false;
+            }
+        } else if entry.file_type().is_file()
+            && options.filter.wants(path)
+            && !add(lists, entry.into_path())
+        {
+            return false;
+        }
+    }
+    true
+}
+
+// Returns the folder's files and subfolders in the given order; for tag
+// order, subfolders come after the files
+fn sorted_entries(folder: &Path, order: Order) -> Vec<DirEntry> {
+    let mut entries: Vec<DirEntry> = WalkDir::new(folder)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .collect();
+    match order {
+        Order::FileName => {
+            entries.sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        }
+        Order::Natural => entries.sort_by(natural_cmp),
+        Order::Tags => {
+            let mut keyed: Vec<((bool, i32, i32), DirEntry)> = entries
+                .into_iter()
+                .map(|entry| (tag_key(&entry), entry))
+                .collect();
+            keyed.sort_by(|(k1, e1), (k2, e2)| {
+                k1.cmp(k2).then_with(|| natural_cmp(e1, e2))
+            });
+            entries = keyed.into_iter().map(|(_, entry)| entry).collect();
+        }
+    }
+    entries
+}
+
+fn natural_cmp(a: &DirEntry, b: &DirEntry) -> Ordering {
+    util::natural_cmp(
+        &a.file_name().to_string_lossy(),
+        &b.file_name().to_string_lossy(),
+    )
+}
+
+// Returns (is folder, disc, track number) with 0 for missing numbers
+fn tag_key(entry: &DirEntry) -> (bool, i32, i32) {
+    if entry.file_type().is_dir() {
+        (true, 0, 0)
+    } else if util::is_audio_file(entry.path()) {
+        match util::get_track_tag(entry.path()) {
+            Ok(Some(data)) => (false, data.disc, data.number),
+            _ => (false, 0, 0),
+        }
+    } else {
+        (false, 0, 0)
+    }
+}
+
+// Decides which files to add as tracks and counts those it skips
+#[derive(Default)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    hidden: usize,
+    non_audio: usize,
+    excluded: usize,
+}
+
+impl Filter {
+    // Patterns are separated by semicolons, e.g., "*.flac; *.ogg"; an
+    // empty include means include every audio file
+    pub fn new(include: &str, exclude: &str) -> Result<Self> {
+        Ok(Self {
+            include: patterns(include)?,
+            exclude: patterns(exclude)?,
+            ..Default::default()
+        })
+    }
+
+    // Returns true if the file should be added as a track; otherwise
+    // counts why it was skipped
+    pub fn wants(&mut self, path: &Path) -> bool {
+        match self.reason_to_skip(path) {
+            None => true,
+            Some(Skip::Hidden) => {
+                self.hidden += 1;
+                false
+            }
+            Some(Skip::NonAudio) => {
+                self.non_audio += 1;
+                false
+            }
+            Some(Skip::Excluded) => {
+                self.excluded += 1;
+                false
+            }
+        }
+    }
+
+    fn reason_to_skip(&self, path: &Path) -> Option<Skip> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.starts_with('.') {
+            Some(Skip::Hidden)
+        } else if !util::is_audio_file(path) {
+            Some(Skip::NonAudio)
+        } else if (!self.include.is_empty()
+            && !self.include.iter().any(|p| p.matches_with(&name, OPTIONS)))
+            || self.exclude.iter().any(|p| p.matches_with(&name, OPTIONS))
+        {
+            Some(Skip::Excluded)
+        } else {
+            None
+        }
+    }
+
+    // Returns how many tracks were added and what was skipped, e.g.,
+    // "Added 12 tracks; skipped 3 non-audio and 1 hidden files"
+    pub fn summary(&self, added: usize) -> String {
+        let mut skipped = vec![];
+        for (count, what) in [
+            (self.non_audio, "non-audio"),
+            (self.hidden, "hidden"),
+            (self.excluded, "excluded"),
+        ] {
+            if count > 0 {
+                skipped.push(format!("{count} {what}"));
+            }
+        }
+        let mut text = format!("Added {added} tracks");
+        if let Some(last) = skipped.pop() {
+            text.push_str("; skipped ");
+            if !skipped.is_empty() {
+                text.push_str(&skipped.join(", "));
+                text.push_str(" and ");
+            }
+            text.push_str(&last);
+            text.push_str(" files");
+        }
+        text
+    }
+}
+
+// Returns true for entries (files or folders) whose names begin with '.'
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+fn patterns(text: &str) -> Result<Vec<Pattern>> {
+    let mut patterns = vec![];
+    for pattern in text.split(';').map(str::trim) {
+        if !pattern.is_empty() {
+            patterns.push(Pattern::new(pattern)?);
+        }
+    }
+    Ok(patterns)
+}
+
+enum Skip {
+    Hidden,
+    NonAudio,
+    Excluded,
+}
+
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
@@ -0,0 +1,255 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::fixed::Action;
+use crate::folder_import;
+use crate::model::{Track, TrackID, TreePath};
+use crate::progress_form;
+use crate::util;
+use fltk::{app::Sender, tree::TreeItem};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+use tlmlib::{playlists, Item, List, TrackForTID};
+
+// What to import
+pub enum Source {
+    Folder(PathBuf, folder_import::Options),
+    Playlist(PathBuf),
+    PlaylistFolder(PathBuf), // every .m3u(8) in the folder as lists
+}
+
+// A track and the names of the sublists (of the list being imported into)
+// that it belongs in
+pub type Addition = (Vec<String>, Track);
+
+pub enum Message {
+    Batch(Vec<Addition>),
+    Done(String), // a summary of what was added and skipped
+    Failed(String),
+}
+
+// Imports in a worker thread that sends batches of tracks as Messages,
+// telling the GUI with Action::ImportBatch; the GUI adds each batch in
+// one go so the model is always consistent even if the import is
+// cancelled (by dropping the Import)
+pub struct Import {
+    pub receiver: mpsc::Receiver<Message>,
+    cancelled: Arc<AtomicBool>,
+    // The list being imported into (or TOP_LEVEL_NAME) and the tree paths
+    // of its sublists made so far
+    pub treepath: TreePath,
+    pub lists: HashMap<Vec<String>, TreePath>,
+    pub first: Option<(TreePath, TreeItem)>,
+    pub tids: Vec<TrackID>, // of the tracks added so far
+    pub form: progress_form::Form,
+}
+
+impl Import {
+    pub fn new(
+        source: Source,
+        treepath: TreePath,
+        title: &str,
+        sender: Sender<Action>,
+    ) -> Self {
+        let (messages, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let cancelled = Arc::clone(&cancelled);
+            move || {
+                let mut batcher = Batcher {
+                    batch: vec![],
+                    added: 0,
+                    messages,
+                    sender,
+                    cancelled,
+                };
+                if let Some(message) = import(source, &mut batcher) {
+                    if batcher.flush() {
+                        batcher.send(message);
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            cancelled,
+            treepath,
+            lists: HashMap::new(),
+            first: None,
+            tids: vec![],
+            form: progress_form::Form::new(title, sender),
+        }
+    }
+}
+
+impl Drop for Import {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Returns the final message, or None if cancelled
+fn import(source: Source, batcher: &mut Batcher) -> Option<Message> {
+    match source {
+        Source::Folder(folder, mut options) => {
+            if !folder_import::read_folder(
+                &folder,
+                &mut options,
+                &mut |lists, path| {
+                    batcher.add(lists, Track::new(path, 0.0))
+                },
+            ) {
+                return None;
+            }
+            Some(Message::Done(options.filter.summary(batcher.added)))
+        }
+        Source::Playlist(playlist) => {
+            match playlists::read_playlist(&playlist) {
+                Ok((tracks, skipped)) => {
+                    for track in tracks {
+                        if !batcher.add(&[], track) {
+                            return None;
+                        }
+                    }
+                    Some(Message::Done(summary(batcher.added, 0, &skipped)))
+                }
+                Err(err) => Some(Message::Failed(err.to_string())),
+            }
+        }
+        Source::PlaylistFolder(folder) => {
+            match playlists::read_playlist_folder(&folder) {
+                Ok((tlm, skipped)) => {
+                    let mut unreadable = 0;
+                    if !import_list(
+                        &tlm.root,
+                        &tlm.track_for_tid,
+                        &mut vec![],
+                        &mut unreadable,
+                        batcher,
+                    ) {
+                        return None;
+                    }
+                    Some(Message::Done(summary(
+                        batcher.added,
+                        unreadable,
+                        &skipped,
+                    )))
+                }
+                Err(err) => Some(Message::Failed(err.to_string())),
+            }
+        }
+    }
+}
+
+// Returns how many tracks were added and what was skipped, e.g., "Added
+// 12 tracks; skipped 2 unreadable tracks; skipped: missing track: …";
+// only the first few of the playlists' skipped entries are listed
+fn summary(added: usize, unreadable: usize, skipped: &[String]) -> String {
+    let mut text = format!("Added {added} tracks");
+    if unreadable > 0 {
+        text.push_str(&format!("; skipped {unreadable} unreadable tracks"));
+    }
+    if !skipped.is_empty() {
+        text.push_str("; skipped:");
+        for what in skipped.iter().take(MAX_SKIPPED_SHOWN) {
+            text.push_str(&format!("<br>{}", util::html_escaped(what)));
+        }
+        if skipped.len() > MAX_SKIPPED_SHOWN {
+            text.push_str(&format!(
+                "<br>and {} more",
+                skipped.len() - MAX_SKIPPED_SHOWN
+            ));
+        }
+    }
+    text
+}
+
+// Adds the list's tracks with their durations read from the tracks
+// themselves; tracks whose durations can't be read are skipped
+fn import_list(
+    list: &List,
+    track_for_tid: &TrackForTID,
+    lists: &mut Vec<String>,
+    skipped: &mut usize,
+    batcher: &mut Batcher,
+) -> bool {
+    for item in &list.items {
+        match item {
+            Item::List(sublist) => {
+                lists.push(sublist.name.clone());
+                let ok = import_list(
+                    sublist,
+                    track_for_tid,
+                    lists,
+                    skipped,
+                    batcher,
+                );
+                lists.pop();
+                if !ok {
+                    return false;
+                }
+            }
+            Item::Track(tid) => {
+                if let Some(track) = track_for_tid.get(tid) {
+                    match util::get_track_secs(&track.filename) {
+                        Ok(secs) => {
+                            let track =
+                                Track::new(track.filename.clone(), secs);
+                            if !batcher.add(lists, track) {
+                                return false;
+                            }
+                        }
+                        Err(_) => *skipped += 1,
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+
+struct Batcher {
+    batch: Vec<Addition>,
+    added: usize,
+    messages: mpsc::Sender<Message>,
+    sender: Sender<Action>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Batcher {
+    // Returns false if the import has been cancelled
+    fn add(&mut self, lists: &[String], track: Track) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.batch.push((lists.to_vec(), track));
+        self.added += 1;
+        self.batch.len() < BATCH_SIZE || self.flush()
+    }
+
+    fn flush(&mut self) -> bool {
+        if self.batch.is_empty() {
+            return true;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.send(Message::Batch(batch))
+    }
+
+    fn send(&mut self, message: Message) -> bool {
+        if self.messages.send(message).is_err() {
+            return false; // the Import has been dropped
+        }
+        self.sender.send(Action::ImportBatch);
+        true
+    }
+}
+
+const BATCH_SIZE: usize = 50;
+const MAX_SKIPPED_SHOWN: usize = 3;
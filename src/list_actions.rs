@@ -2,14 +2,23 @@
 // License: GPLv3
 
 use crate::application::Application;
-use crate::fixed::APPNAME;
-use crate::model::{Track, TrackID};
+use crate::export_form;
+use crate::fixed::{APPNAME, INFO_TIMEOUT, TOP_LEVEL_NAME};
+use crate::folder_import;
+use crate::import_worker::{self, Message};
+use crate::model::{Track, TrackID, TreePath};
 use crate::new_list_form;
-use crate::playlists;
-use crate::util;
-use anyhow::anyhow;
-use fltk::{dialog, prelude::*};
-use std::path::Path;
+use crate::util::{self, PathBufExt};
+use fltk::{
+    dialog::{self, FileDialog, FileDialogType},
+    prelude::*,
+    tree::TreeItem,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+use tlmlib::playlists::{self, Entry};
 
 impl Application {
     pub(crate) fn on_list_add(&mut self) {
@@ -27,9 +36,15 @@ impl Application {
             new_list_form::Form::new(&self.current.track, &top_levels);
         if *form.ok.borrow() {
             let name = &*form.name.borrow();
+            // The form gives the parent list's name but lists are added
+            // by tree path
             let parent_list = &*form.parent_list.borrow();
+            let parent_list = &if parent_list == TOP_LEVEL_NAME {
+                parent_list.to_string()
+            } else {
+                util::treepath_escaped(parent_list)
+            };
             let folder_or_playlist = &*form.folder_or_playlist.borrow();
-            let include_subdirs = *form.include_subdirs.borrow();
             if !folder_or_playlist.exists() {
                 self.new_empty_list(parent_list, name);
             } else if folder_or_playlist.is_file() {
@@ -39,11 +54,29 @@ impl Application {
                     folder_or_playlist,
                 );
             } else if folder_or_playlist.is_dir() {
+                let filter = match folder_import::Filter::new(
+                    &form.include.borrow(),
+                    &form.exclude.borrow(),
+                ) {
+                    Ok(filter) => filter,
+                    Err(err) => {
+                        util::popup_error_message(&format!(
+                            "Invalid pattern: {err}"
+                        ));
+                        return;
+                    }
+                };
+                let options = folder_import::Options {
+                    include_subdirs: *form.include_subdirs.borrow(),
+                    nested_subdirs: *form.nested_subdirs.borrow(),
+                    order: *form.order.borrow(),
+                    filter,
+                };
                 self.new_list_from_folder(
                     parent_list,
                     name,
                     folder_or_playlist,
-                    include_subdirs,
+                    options,
                 );
             }
         }
@@ -59,7 +92,7 @@ impl Application {
         }
     }
 
-    fn new_list_from_playlist(
+    pub(crate) fn new_list_from_playlist(
         &mut self,
         parent_list: &str,
         name: &str,
@@ -70,91 +103,275 @@ impl Application {
         } else {
             util::sanitize(name, "New List")
         };
-        if let Some((treepath, item)) =
-            self.tlm.add_empty_list(parent_list, &name)
-        {
-            static MESSAGE: &str = "can only read .m3u playlists";
-            let reply = match playlist.extension() {
-                Some(suffix) => {
-                    if let Some(suffix) = suffix.to_str() {
-                        match suffix {
-                            "m3u" | "M3U" => playlists::read_m3u(playlist),
-                            _ => Err(anyhow!(MESSAGE)),
-                        }
-                    } else {
-                        Err(anyhow!(MESSAGE))
-                    }
-                }
-                None => Err(anyhow!(MESSAGE)),
-            };
-            let mut first = None;
-            match reply {
-                Ok(tracks) => {
-                    for track in tracks {
-                        if let Some((path, item)) =
-                            self.tlm.add_track(&treepath, track)
-                        {
-                            if first.is_none() {
-                                first = Some((path, item));
-                            }
-                        }
-                    }
-                    self.tlm.clear_selection();
-                    if let Some((treepath, item)) = first {
-                        self.select_track_in_tree(treepath, item);
-                    } else {
-                        self.select_track_in_tree(treepath, item);
-                    }
-                    self.update_ui();
-                }
-                Err(err) => util::popup_error_message(&err.to_string()),
-            };
-        }
+        self.new_list_from_source(
+            parent_list,
+            &name,
+            import_worker::Source::Playlist(playlist.to_path_buf()),
+        );
     }
 
-    fn new_list_from_folder(
+    pub(crate) fn new_list_from_folder(
         &mut self,
         parent_list: &str,
         name: &str,
         folder: &Path,
-        include_subdirs: bool,
+        options: folder_import::Options,
     ) {
         let name = if name.is_empty() {
             util::canonicalize(folder)
         } else {
             util::sanitize(name, "New List")
         };
+        self.new_list_from_source(
+            parent_list,
+            &name,
+            import_worker::Source::Folder(folder.to_path_buf(), options),
+        );
+    }
+
+    fn new_list_from_source(
+        &mut self,
+        parent_list: &str,
+        name: &str,
+        source: import_worker::Source,
+    ) {
+        if self.import_in_progress() {
+            return;
+        }
         if let Some((treepath, item)) =
-            self.tlm.add_empty_list(parent_list, &name)
+            self.tlm.add_empty_list(parent_list, name)
         {
-            let walker = if include_subdirs {
-                walkdir::WalkDir::new(folder).sort_by_file_name()
-            } else {
-                walkdir::WalkDir::new(folder)
-                    .sort_by_file_name()
-                    .max_depth(1)
-            };
-            let mut first = None;
-            for entry in walker.into_iter().filter_map(|e| e.ok()) {
-                if !entry.path().is_file() {
-                    continue;
+            self.tlm.clear_selection();
+            self.select_track_in_tree(treepath.clone(), item);
+            self.update_ui();
+            self.import = Some(import_worker::Import::new(
+                source,
+                treepath,
+                "Add List",
+                self.sender,
+            ));
+        }
+    }
+
+    pub(crate) fn on_list_export(&mut self) {
+        let item = match self.selected_list_item() {
+            Some(item) => item,
+            None => {
+                util::popup_error_message("Select a list to export");
+                return;
+            }
+        };
+        let name = item.label().unwrap_or_else(|| "List".to_string());
+        let form = export_form::Form::new(&name);
+        if !*form.ok.borrow() {
+            return;
+        }
+        let include_sublists = *form.include_sublists.borrow();
+        let relative = *form.relative.borrow();
+        let filename = export_form::playlist_filename(&name);
+        if filename.is_empty() {
+            return;
+        }
+        // Playlists can't play part of a file, so virtual tracks (e.g.,
+        // from a CUE sheet) are exported as their whole file, once
+        let mut virtual_files = HashSet::<PathBuf>::default();
+        let mut virtuals = 0;
+        let entries: Vec<Entry> = self
+            .tlm
+            .list_for_item(&item)
+            .tids(include_sublists)
+            .iter()
+            .filter_map(|tid| self.tlm.track_for_tid.get(tid))
+            .filter(|track| {
+                if !track.is_virtual() {
+                    return true;
                 }
-                if let Some((path, item)) = self.tlm.add_track(
-                    &treepath,
-                    Track::new(entry.into_path(), 0.0),
-                ) {
-                    if first.is_none() {
-                        first = Some((path, item));
+                virtuals += 1;
+                virtual_files.insert(track.filename.clone())
+            })
+            .map(util::playlist_entry)
+            .collect();
+        match playlists::write_playlist(&filename, &entries, relative) {
+            Ok(()) => {
+                let note = if virtuals > 0 {
+                    format!(
+                        "<br><font color=green>Exported {virtuals} virtual
+                        tracks as their {} whole files.</font>",
+                        virtual_files.len()
+                    )
+                } else {
+                    String::new()
+                };
+                self.info_view.set_value(&format!(
+                    "Exported {} tracks to <font color=navy>{filename:?}</font>{note}",
+                    entries.len()
+                ));
+                self.clear_info_after(INFO_TIMEOUT);
+            }
+            Err(err) => util::popup_error_message(&format!(
+                "Failed to export: {err}"
+            )),
+        }
+    }
+
+    pub(crate) fn on_list_import_folder(&mut self) {
+        if self.import_in_progress() {
+            return;
+        }
+        let mut form = FileDialog::new(FileDialogType::BrowseDir);
+        form.set_title(&format!("Import Playlist Folder — {APPNAME}"));
+        let _ =
+            form.set_directory(&util::get_track_dir(&self.current.track));
+        form.show();
+        let folder = form.filename();
+        if !folder.is_dir() {
+            return;
+        }
+        self.import = Some(import_worker::Import::new(
+            import_worker::Source::PlaylistFolder(folder),
+            TOP_LEVEL_NAME.to_string(),
+            "Import Playlist Folder",
+            self.sender,
+        ));
+    }
+
+    // Only one import may run at a time since each adds to its own list
+    // An import adds its tracks to its lists by tree path, so no list may
+    // be moved, renamed, or deleted until the import has finished
+    pub(crate) fn list_locked_by_import(&self, item: &TreeItem) -> bool {
+        let tid = unsafe { item.user_data::<TrackID>() };
+        tid.is_none() && self.import_in_progress()
+    }
+
+    fn import_in_progress(&self) -> bool {
+        if self.import.is_some() {
+            util::popup_error_message(
+                "Wait for the current import to finish or cancel it",
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    // Adds every batch of tracks the import has sent so far; each batch
+    // is added in one go so the tree is never left half-updated
+    pub(crate) fn on_import_batch(&mut self) {
+        let mut import = match self.import.take() {
+            Some(import) => import,
+            None => return, // cancelled
+        };
+        let messages: Vec<Message> = import.receiver.try_iter().collect();
+        let mut result = None;
+        for message in messages {
+            match message {
+                Message::Batch(additions) => {
+                    for (lists, track) in additions {
+                        self.add_imported_track(&mut import, &lists, track);
                     }
                 }
+                Message::Done(summary) => result = Some(Ok(summary)),
+                Message::Failed(err) => result = Some(Err(err)),
+            }
+        }
+        match result {
+            Some(result) => self.finish_import(import, result),
+            None => {
+                import.form.set_count(import.tids.len());
+                self.tlm.track_tree.redraw();
+                self.import = Some(import);
             }
+        }
+    }
+
+    // Cancelling keeps the tracks that have already been added
+    pub(crate) fn on_import_cancel(&mut self) {
+        if let Some(import) = self.import.take() {
+            let summary = format!(
+                "Import cancelled after adding {} tracks",
+                import.tids.len()
+            );
+            self.finish_import(import, Ok(summary));
+        }
+    }
+
+    fn finish_import(
+        &mut self,
+        mut import: import_worker::Import,
+        result: Result<String, String>,
+    ) {
+        let first = import.first.take();
+        let tids = std::mem::take(&mut import.tids);
+        drop(import); // closes the progress form
+        if let Some((treepath, item)) = first {
             self.tlm.clear_selection();
-            if let Some((treepath, item)) = first {
-                self.select_track_in_tree(treepath, item);
-            } else {
-                self.select_track_in_tree(treepath, item);
+            self.select_track_in_tree(treepath, item);
+        }
+        self.update_ui();
+        self.scan_added_durations(&tids);
+        match result {
+            Ok(summary) => {
+                self.info_view.set_value(&summary);
+                self.clear_info_after(INFO_TIMEOUT);
             }
-            self.update_ui();
+            Err(err) => util::popup_error_message(&format!(
+                "Failed to import: {err}"
+            )),
+        }
+    }
+
+    fn add_imported_track(
+        &mut self,
+        import: &mut import_worker::Import,
+        lists: &[String],
+        track: Track,
+    ) {
+        if let Some(parent) = self.imported_list(import, lists) {
+            if let Some((treepath, item)) =
+                self.tlm.add_track(&parent, track)
+            {
+                if let Some(tid) = unsafe { item.user_data::<TrackID>() } {
+                    import.tids.push(tid);
+                }
+                if import.first.is_none() {
+                    import.first = Some((treepath, item));
+                }
+            }
+        }
+    }
+
+    // Returns the tree path of the import's list or of the sublist named
+    // by lists, adding the sublist (and its parents) if not already added
+    fn imported_list(
+        &mut self,
+        import: &mut import_worker::Import,
+        lists: &[String],
+    ) -> Option<TreePath> {
+        let (name, parents) = match lists.split_last() {
+            Some(split) => split,
+            None => return Some(import.treepath.clone()),
+        };
+        if let Some(treepath) = import.lists.get(lists) {
+            return Some(treepath.clone());
+        }
+        let parent = self.imported_list(import, parents)?;
+        let (treepath, _) = self.tlm.add_empty_list(&parent, name)?;
+        import.lists.insert(lists.to_vec(), treepath.clone());
+        Some(treepath)
+    }
+
+    // Returns the selected list or the list containing the selected track
+    fn selected_list_item(&self) -> Option<TreeItem> {
+        let item = self.tlm.track_tree.first_selected_item()?;
+        let item = if self.tlm.track_for_item(&item).is_some() {
+            item.parent()?
+        } else {
+            item
+        };
+        if item.depth() > 0 {
+            Some(item)
+        } else {
+            None // the root isn't a list
         }
     }
 
@@ -162,7 +379,7 @@ impl Application {
         if let Some(mut item) = self.tlm.track_tree.first_selected_item() {
             let tid = unsafe { item.user_data::<TrackID>() };
             // Lists _can_ be renamed
-            if tid.is_none() {
+            if tid.is_none() && !self.list_locked_by_import(&item) {
                 let old_name =
                     item.label().unwrap_or_else(|| "List".to_string());
                 dialog::message_title(&format!("Rename List — {APPNAME}"));
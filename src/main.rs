@@ -4,19 +4,26 @@
 
 mod actions;
 mod application;
+mod backups;
 mod config;
+mod duration_scan;
 mod edit_actions;
+mod export_form;
 mod file_actions;
 mod fixed;
+mod folder_import;
 mod help_actions;
 mod html_form;
+mod import_worker;
 mod list_actions;
 mod list_form;
 mod main_window;
 mod model;
 mod new_list_form;
 mod options_form;
-mod playlists;
+mod progress_form;
+mod recovery;
+mod relink;
 mod track_actions;
 mod util;
 
@@ -24,7 +31,7 @@ use crate::application::Application;
 use crate::fixed::initialize_time_icons;
 use config::Config;
 use state::Storage;
-use std::{panic, sync};
+use std::{env, panic, path::PathBuf, sync};
 
 pub static CONFIG: Storage<sync::RwLock<Config>> = Storage::new();
 
@@ -35,6 +42,8 @@ fn main() {
     }));
     CONFIG.set(sync::RwLock::new(Config::new()));
     initialize_time_icons();
-    let mut app = Application::new();
+    // A .tlm file to open or a playlist or folder to make a new list from
+    let startup_path = env::args_os().nth(1).map(PathBuf::from);
+    let mut app = Application::new(startup_path);
     app.run();
 }
@@ -127,10 +127,17 @@ fn add_menubar(sender: Sender<Action>, width: i32) -> SysMenuBar {
     menubar.add_emit(
         "&File/Save &As…\t",
         Shortcut::None,
-        MenuFlag::MenuDivider,
+        MenuFlag::Normal,
         sender,
         Action::FileSaveAs,
     );
+    menubar.add_emit(
+        "&File/Restore &Backup…\t",
+        Shortcut::None,
+        MenuFlag::MenuDivider,
+        sender,
+        Action::FileRestoreBackup,
+    );
     menubar.add_emit(
         "&File/&Configure…\t",
         Shortcut::None,
@@ -190,10 +197,17 @@ fn add_menubar(sender: Sender<Action>, width: i32) -> SysMenuBar {
     menubar.add_emit(
         "&Edit/D&elete\t",
         Shortcut::None,
-        MenuFlag::Normal,
+        MenuFlag::MenuDivider,
         sender,
         Action::EditDelete,
     );
+    menubar.add_emit(
+        "&Edit/Re&write Track Paths…\t",
+        Shortcut::None,
+        MenuFlag::Normal,
+        sender,
+        Action::EditRewritePaths,
+    );
     menubar.add_emit(
         "&List/&Add…\t",
         Shortcut::Ctrl | 'l',
@@ -201,6 +215,20 @@ fn add_menubar(sender: Sender<Action>, width: i32) -> SysMenuBar {
         sender,
         Action::ListAdd,
     );
+    menubar.add_emit(
+        "&List/&Export…\t",
+        Shortcut::None,
+        MenuFlag::Normal,
+        sender,
+        Action::ListExport,
+    );
+    menubar.add_emit(
+        "&List/&Import Playlist Folder…\t",
+        Shortcut::None,
+        MenuFlag::Normal,
+        sender,
+        Action::ListImportFolder,
+    );
     menubar.add_emit(
         "&List/&Rename\t",
         Shortcut::None,
@@ -211,10 +239,17 @@ fn add_menubar(sender: Sender<Action>, width: i32) -> SysMenuBar {
     menubar.add_emit(
         "&Track/&Add…\t",
         Shortcut::Ctrl | 't',
-        MenuFlag::MenuDivider,
+        MenuFlag::Normal,
         sender,
         Action::TrackAdd,
     );
+    menubar.add_emit(
+        "&Track/&Check Tracks…\t",
+        Shortcut::None,
+        MenuFlag::MenuDivider,
+        sender,
+        Action::TrackCheck,
+    );
     menubar.add_emit(
         "&Track/Play Pre&vious\t",
         Shortcut::from_key(Key::F4),
@@ -578,11 +613,10 @@ pub fn add_event_handlers(
     });
 }
 
-pub fn update_widgets_from_config(widgets: &mut Widgets) -> bool {
+pub fn update_widgets_from_config(widgets: &mut Widgets) {
     let config = CONFIG.get().read().unwrap();
     widgets.volume_slider.set_value(config.volume);
     widgets
         .volume_label
         .set_label(&format!("{}%", (config.volume * 100.0).round()));
-    config.last_file.exists()
 }
@@ -1,34 +1,40 @@
 // Copyright © 2022 Mark Summerfield. All rights reserved.
 // License: GPLv3
 
-use crate::fixed::{MAX_HISTORY_SIZE, TIME_ICONS, TOP_LEVEL_NAME};
+use crate::backups;
+use crate::duration_scan::Job;
+use crate::fixed::{APPNAME, MAX_HISTORY_SIZE, TIME_ICONS, TOP_LEVEL_NAME};
+use crate::relink::Missing;
 use crate::util;
 use anyhow::{bail, Result};
-use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use fltk::{
     enums::Color,
     image::SvgImage,
     tree::{Tree, TreeItem},
 };
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{
+    hash_map::DefaultHasher, HashMap, HashSet, VecDeque,
+};
 use std::{
     collections::vec_deque::Iter,
-    fs::File,
-    io::prelude::*,
-    io::BufReader,
+    fs,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
-    str::FromStr,
+    time::SystemTime,
 };
+use tlmlib::{
+    Item, List, Repair, Tlm, TrackForTID, TrackPaths, FORMAT_VERSION,
+    INVALID_TID,
+};
+pub use tlmlib::{Track, TrackID, TreePath};
 
-pub type TreePath = String;
-pub type TrackID = i32;
-type TrackForTID = HashMap<TrackID, Track>;
-const INVALID_TID: i32 = -1;
-
+// start and end are the offsets of a virtual track's part of its file
 pub struct Current {
     pub treepath: TreePath,
     pub track: PathBuf,
     pub tid: TrackID,
+    pub start: f64,
+    pub end: f64,
 }
 
 impl Default for Current {
@@ -37,6 +43,8 @@ impl Default for Current {
             treepath: TreePath::new(),
             track: PathBuf::new(),
             tid: INVALID_TID,
+            start: 0.0,
+            end: 0.0,
         }
     }
 }
@@ -49,25 +57,39 @@ impl Current {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Track {
-    pub filename: PathBuf,
-    pub secs: f64,
-}
-
-impl Track {
-    pub fn new(filename: PathBuf, secs: f64) -> Self {
-        Self { filename, secs }
-    }
-}
-
 pub struct Model {
     pub filename: PathBuf,
     pub track_tree: Tree,
     pub track_for_tid: TrackForTID,
     pub next_tid: TrackID,
+    pub track_paths: TrackPaths,
+    pub backups: usize, // how many backups to keep when saving
+    pub version: u32,   // the .tlm format version it was loaded from
     history: VecDeque<TreePath>,
     dirty: bool,
+    generation: u64, // counts the changes so that they can be noticed
+    stamp: Option<Stamp>,
+}
+
+// What the .tlm file was like on disk when it was last loaded or saved
+#[derive(Clone, Copy)]
+struct Stamp {
+    modified: SystemTime,
+    hash: u64,
+}
+
+impl Stamp {
+    fn new(filename: &Path) -> Option<Self> {
+        let modified = fs::metadata(filename).and_then(|m| m.modified());
+        match (modified, fs::read(filename)) {
+            (Ok(modified), Ok(bytes)) => {
+                let mut hasher = DefaultHasher::new();
+                bytes.hash(&mut hasher);
+                Some(Self { modified, hash: hasher.finish() })
+            }
+            _ => None,
+        }
+    }
 }
 
 impl Model {
@@ -77,8 +99,13 @@ impl Model {
             track_tree,
             track_for_tid: TrackForTID::default(),
             next_tid: 1,
+            track_paths: TrackPaths::default(),
+            backups: 0,
+            version: FORMAT_VERSION,
             history: VecDeque::default(),
             dirty: false,
+            generation: 0,
+            stamp: None,
         }
     }
 
@@ -87,33 +114,44 @@ impl Model {
             self.filename = filename.to_path_buf();
         }
         self.clear();
-        let text = self.get_text()?;
-        self.parse(text)
+        let tlm = Tlm::load_with(&self.filename, self.track_paths.clone())?;
+        self.mirror(tlm);
+        self.stamp = Stamp::new(&self.filename);
+        Ok(())
     }
 
-    fn get_text(&self) -> Result<String> {
-        let compressed = self.is_compressed()?;
-        let mut text = String::new();
-        let file = File::open(&self.filename)?;
-        if compressed {
-            let mut gz = GzDecoder::new(file);
-            gz.read_to_string(&mut text)?;
-        } else {
-            let mut buffer = BufReader::new(file);
-            buffer.read_to_string(&mut text)?;
-        }
-        Ok(text)
+    // Replaces the lists, tracks, and history with those of a backup or
+    // recovery file in the same folder, keeping the filename; the model is
+    // dirty so that saving keeps what was restored
+    pub fn restore(&mut self, from: &Path) -> Result<()> {
+        self.clear();
+        let tlm = Tlm::load_with(from, self.track_paths.clone())?;
+        self.mirror(tlm);
+        self.set_dirty();
+        Ok(())
     }
 
-    fn is_compressed(&self) -> Result<bool> {
-        let mut file = File::open(&self.filename)?;
-        let mut buffer = [0; 2]; // 0x1F 0x8B gzip magic
-        file.read_exact(&mut buffer)?;
-        Ok(buffer[0] == 0x1F && buffer[1] == 0x8B)
+    // Loads as much of a damaged file as can be read and returns what was
+    // skipped or repaired; if anything was, the model is dirty so that
+    // it gets saved cleanly
+    pub fn load_lenient(&mut self, filename: &Path) -> Result<Vec<Repair>> {
+        if filename.exists() {
+            self.filename = filename.to_path_buf();
+        }
+        self.clear();
+        let (tlm, repairs) =
+            Tlm::load_lenient(&self.filename, self.track_paths.clone())?;
+        self.mirror(tlm);
+        self.stamp = Stamp::new(&self.filename);
+        if !repairs.is_empty() {
+            self.set_dirty();
+        }
+        Ok(repairs)
     }
 
     pub fn clear(&mut self) {
         self.dirty = false;
+        self.version = FORMAT_VERSION;
         self.track_for_tid.clear();
         self.history.clear();
         self.track_tree.clear();
@@ -137,25 +175,25 @@ impl Model {
             MAX_HISTORY_SIZE,
         );
         if let Some(track) = self.track_for_tid.get_mut(&tid) {
-            if !util::isclose64(secs, track.secs) {
+            if !util::same_secs(secs, track.secs) {
                 track.secs = secs;
                 changed = true;
             }
         }
         if changed {
-            self.dirty = true;
+            self.set_dirty();
         }
         changed
     }
 
     pub fn history_delete_item(&mut self, index: usize) {
         self.history.remove(index);
-        self.dirty = true;
+        self.set_dirty();
     }
 
     pub fn history_shrink(&mut self) {
         self.history.truncate(1);
-        self.dirty = true;
+        self.set_dirty();
     }
 
     pub fn history_iter(&self) -> Iter<TreePath> {
@@ -170,108 +208,132 @@ impl Model {
         self.dirty
     }
 
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     pub fn set_dirty(&mut self) {
         self.dirty = true;
+        self.generation += 1;
     }
 
-    fn parse(&mut self, text: String) -> Result<()> {
-        let mut treepath: Vec<TreePath> = vec![];
-        let mut state = State::WantMagic;
-        let mut lists_seen = HashSet::<TreePath>::default();
-        let mut tracks_seen = HashSet::<TreePath>::default();
-        for (i, line) in text.lines().enumerate() {
-            let lino = i + 1;
-            if line.is_empty() {
-                continue;
+    // Returns each missing track file once, sorted by filename
+    pub fn missing_tracks(&self) -> Vec<Missing> {
+        let mut seen = HashSet::new();
+        let mut missing = vec![];
+        for track in self.track_for_tid.values() {
+            if !track.filename.exists() && seen.insert(&track.filename) {
+                missing.push(Missing {
+                    filename: track.filename.clone(),
+                    // A virtual track's secs are only part of its file
+                    secs: if track.is_virtual() { 0.0 } else { track.secs },
+                });
             }
-            if state == State::InTracks && line == "\x0CHISTORY" {
-                state = State::InHistory;
-            } else if state == State::WantMagic {
-                if !line.starts_with("\x0CTLM\t") {
-                    bail!("error:{lino}: not a .tlm file");
-                }
-                // We ignore the version for now
-                state = State::WantTrackHeader;
-            } else if state == State::WantTrackHeader {
-                if !line.starts_with("\x0CTRACKS") {
-                    bail!("error:{lino}: missing TRACKS");
-                }
-                state = State::InTracks;
-            } else if state == State::InTracks {
-                if line.starts_with(INDENT) {
-                    self.read_list(&mut treepath, &mut lists_seen, line);
-                } else {
-                    self.read_track(
-                        &treepath,
-                        &mut tracks_seen,
-                        lino,
-                        line,
-                    )?;
-                }
-            } else if state == State::InHistory {
-                self.history.push_back(line.to_string());
-            } else {
-                bail!("error:{lino}: invalid .tlm file");
+        }
+        missing.sort_by(|a, b| a.filename.cmp(&b.filename));
+        missing
+    }
+
+    // Replaces each missing file with the one found for it; returns how
+    // many tracks were relinked
+    pub fn relink(&mut self, found: &[(PathBuf, PathBuf)]) -> usize {
+        let new_for_old: HashMap<&PathBuf, &PathBuf> =
+            found.iter().map(|(old, new)| (old, new)).collect();
+        let mut count = 0;
+        for track in self.track_for_tid.values_mut() {
+            if let Some(new) = new_for_old.get(&track.filename) {
+                track.filename = new.to_path_buf();
+                count += 1;
             }
         }
-        Ok(())
+        if count > 0 {
+            self.set_dirty();
+        }
+        count
     }
 
-    fn read_list(
+    // Replaces the from prefix of every track path that has it with to;
+    // returns how many paths were rewritten
+    pub fn rewrite_paths(&mut self, from: &Path, to: &Path) -> usize {
+        let count = util::rewrite_paths(&mut self.track_for_tid, from, to);
+        if count > 0 {
+            self.set_dirty();
+        }
+        count
+    }
+
+    // Copies the lists, tracks, and history into the model and populates
+    // the tree to match
+    fn mirror(&mut self, tlm: Tlm) {
+        let Tlm { root, track_for_tid, next_tid, history, version, .. } =
+            tlm;
+        self.version = version;
+        self.track_for_tid = track_for_tid;
+        self.next_tid = next_tid;
+        self.history = history;
+        let mut lists_seen = HashSet::<TreePath>::default();
+        let mut tracks_seen = HashSet::<TreePath>::default();
+        self.mirror_list(
+            &root,
+            &mut vec![],
+            &mut lists_seen,
+            &mut tracks_seen,
+        );
+    }
+
+    fn mirror_list(
         &mut self,
+        list: &List,
         treepath: &mut Vec<TreePath>,
         lists_seen: &mut HashSet<TreePath>,
-        line: &str,
+        tracks_seen: &mut HashSet<TreePath>,
     ) {
-        let name = line.trim_start_matches(INDENT);
-        let indent = line.len() - name.len();
-        let prev_indent = treepath.len();
-        // if indent > prev_indent ∴ child, so just append
-        if indent == 1 {
-            treepath.clear();
-        } else if indent <= prev_indent {
-            // ∴ same level or higher
-            for _ in 0..(prev_indent - indent + 1) {
-                treepath.pop();
+        for item in &list.items {
+            match item {
+                Item::List(sublist) => {
+                    treepath.push(util::treepath_escaped(&sublist.name));
+                    // Needed to ensure that empty lists are shown
+                    let full_treepath = treepath.join("/");
+                    if !lists_seen.contains(&full_treepath) {
+                        self.track_tree.add(&full_treepath);
+                        lists_seen.insert(full_treepath);
+                    }
+                    self.mirror_list(
+                        sublist,
+                        treepath,
+                        lists_seen,
+                        tracks_seen,
+                    );
+                    treepath.pop();
+                }
+                Item::Track(tid) => {
+                    self.mirror_track(*tid, treepath, tracks_seen)
+                }
             }
         }
-        treepath.push(name.to_string());
-        // Needed to ensure that empty lists are shown
-        let full_treepath = treepath.join("/");
-        if !lists_seen.contains(&full_treepath) {
-            self.track_tree.add(&full_treepath);
-            lists_seen.insert(full_treepath);
-        }
     }
 
-    fn read_track(
+    fn mirror_track(
         &mut self,
+        tid: TrackID,
         treepath: &[TreePath],
         tracks_seen: &mut HashSet<TreePath>,
-        lino: usize,
-        line: &str,
-    ) -> Result<()> {
-        if let Some((filename, secs)) = line.split_once(TAB) {
-            let secs = f64::from_str(secs).unwrap_or(0.0);
-            let filename = PathBuf::from(filename);
-            self.track_for_tid
-                .insert(self.next_tid, Track::new(filename.clone(), secs));
+    ) {
+        if let Some(track) = self.track_for_tid.get(&tid) {
+            let name = util::treepath_escaped(&util::track_name(track));
+            let secs = track.secs;
             let treepath = self.full_treepath(
-                self.next_tid,
+                tid,
                 &treepath.join("/"),
-                &filename,
+                &name,
                 tracks_seen,
             );
             if let Some(mut item) = self.track_tree.add(&treepath) {
                 item.set_label_fgcolor(Color::from_hex(0x000075));
-                item.set_user_data(self.next_tid);
+                item.set_user_data(tid);
                 let icon = image_for_secs(secs);
                 item.set_user_icon(Some(icon));
             }
-            self.next_tid += 1;
-            Ok(())
-        } else {
-            bail!("error:{lino}: failed to read track {line}");
         }
     }
 
@@ -279,11 +341,10 @@ impl Model {
         &mut self,
         tid: TrackID,
         treepath: &str,
-        filename: &Path,
+        name: &str,
         tracks_seen: &mut HashSet<TreePath>,
     ) -> TreePath {
-        let mut full_treepath =
-            format!("{}/{}", treepath, util::canonicalize(filename));
+        let mut full_treepath = format!("{treepath}/{name}");
         if tracks_seen.contains(&full_treepath) {
             full_treepath.push(' ');
             full_treepath.push('(');
@@ -300,12 +361,13 @@ impl Model {
         parent: &str,
         name: &str,
     ) -> Option<(TreePath, TreeItem)> {
+        let name = util::treepath_escaped(name);
         let treepath = if parent == TOP_LEVEL_NAME {
-            name.to_string()
+            name
         } else {
             format!("{parent}/{name}")
         };
-        self.dirty = true;
+        self.set_dirty();
         self.track_tree.add(&treepath).map(|item| (treepath, item))
     }
 
@@ -315,15 +377,18 @@ impl Model {
         track: Track,
     ) -> Option<(TreePath, TreeItem)> {
         self.track_for_tid.insert(self.next_tid, track.clone());
-        let treepath =
-            format!("{}/{}", treepath, util::canonicalize(&track.filename));
+        let treepath = format!(
+            "{}/{}",
+            treepath,
+            util::treepath_escaped(&util::track_name(&track))
+        );
         if let Some(mut item) = self.track_tree.add(&treepath) {
             item.set_label_fgcolor(Color::from_hex(0x000075));
             item.set_user_data(self.next_tid);
             let icon = image_for_secs(track.secs);
             item.set_user_icon(Some(icon));
             self.next_tid += 1;
-            self.dirty = true;
+            self.set_dirty();
             Some((treepath, item))
         } else {
             None
@@ -332,53 +397,193 @@ impl Model {
 
     pub fn save(&mut self) -> Result<()> {
         assert!(!self.filename.as_os_str().is_empty());
-        let file = File::create(&self.filename)?;
-        let mut gz = GzEncoder::new(file, Compression::best());
-        gz.write_all(b"\x0CTLM\t100\n\x0CTRACKS\n")?;
+        if self.version > FORMAT_VERSION {
+            bail!(
+                "this file is from a newer version of {APPNAME} so saving \
+                it would lose whatever this version skipped; use File→Save \
+                As instead"
+            );
+        }
+        backups::back_up(&self.filename, self.backups)?;
+        self.to_tlm().save(&self.filename)?;
+        self.version = FORMAT_VERSION;
+        self.dirty = false;
+        self.stamp = Stamp::new(&self.filename);
+        Ok(())
+    }
+
+    // Returns true if the file has been changed on disk (e.g., by someone
+    // else or by a script) since it was last loaded or saved
+    pub fn changed_on_disk(&mut self) -> bool {
+        let stamp = match self.stamp {
+            Some(stamp) => stamp,
+            None => return false, // new, or unreadable when loaded
+        };
+        match fs::metadata(&self.filename).and_then(|m| m.modified()) {
+            Ok(modified) if modified != stamp.modified => {
+                match Stamp::new(&self.filename) {
+                    Some(now) if now.hash == stamp.hash => {
+                        self.stamp = Some(now); // only touched
+                        false
+                    }
+                    Some(_) => true,
+                    None => false, // e.g., it is being replaced
+                }
+            }
+            _ => false, // unchanged or deleted
+        }
+    }
+
+    // Adds the lists and tracks that are in the file on disk but not in the
+    // model (e.g., that someone else added); returns how many were added
+    pub fn merge_from_disk(&mut self) -> Result<usize> {
+        let theirs =
+            Tlm::load_with(&self.filename, self.track_paths.clone())?;
+        let mut tlm = self.to_tlm();
+        let added = tlm.merge(&theirs);
+        let version = self.version.max(theirs.version);
+        self.clear();
+        self.mirror(tlm);
+        self.version = version;
+        self.stamp = Stamp::new(&self.filename);
+        self.set_dirty();
+        Ok(added)
+    }
+
+    // Replaces the lists, tracks, and history with those in the file on
+    // disk, keeping the lists that were open open and the selection
+    // selected if they're still there; the model is left unchanged if the
+    // file can't be read
+    pub fn reload(&mut self) -> Result<()> {
+        let tlm = Tlm::load_with(&self.filename, self.track_paths.clone())?;
+        let mut open = HashSet::<TreePath>::default();
         let mut opt_item = self.track_tree.first();
         while let Some(item) = opt_item {
-            opt_item = item.next();
-            if item.depth() == 0 {
-                continue; // skip "ROOT"
+            if item.has_children() && item.is_open() {
+                open.insert(util::treepath_for_item(Some(item.clone())));
             }
-            let tid = unsafe { item.user_data::<TrackID>() };
-            if let Some(tid) = tid {
-                if let Some(track) = self.track_for_tid.get(&tid) {
-                    gz.write_all(
-                        format!(
-                            "{}\t{:.3}\n",
-                            track.filename.display(),
-                            track.secs
-                        )
-                        .as_bytes(),
-                    )?;
-                }
-            } else {
-                let indent =
-                    INDENT.to_string().repeat(item.depth() as usize);
-                gz.write_all(
-                    format!(
-                        "{indent}{}\n",
-                        item.label().unwrap_or_default()
-                    )
-                    .as_bytes(),
-                )?;
+            opt_item = self.track_tree.next(&item);
+        }
+        let selected = self
+            .track_tree
+            .first_selected_item()
+            .map(|item| util::treepath_for_item(Some(item)));
+        self.clear();
+        self.mirror(tlm);
+        let mut opt_item = self.track_tree.first();
+        while let Some(mut item) = opt_item {
+            if item.depth() > 0
+                && item.has_children()
+                && !open
+                    .contains(&util::treepath_for_item(Some(item.clone())))
+            {
+                item.close();
             }
+            opt_item = self.track_tree.next(&item);
         }
-        gz.write_all("\x0CHISTORY\n".as_bytes())?;
-        for treepath in &self.history {
-            gz.write_all(format!("{treepath}\n").as_bytes())?;
+        if let Some(treepath) = selected {
+            let _ = self
+                .track_tree
+                .select(treepath.trim_end_matches('/'), false);
         }
-        gz.finish()?;
-        self.dirty = false;
+        self.stamp = Stamp::new(&self.filename);
         Ok(())
     }
 
+    // Returns the lists, tracks, and history as currently shown in the
+    // tree
+    pub fn to_tlm(&self) -> Tlm {
+        let mut tlm = Tlm {
+            filename: self.filename.clone(),
+            track_for_tid: self.track_for_tid.clone(),
+            next_tid: self.next_tid,
+            history: self.history.clone(),
+            paths: self.track_paths.clone(),
+            ..Default::default()
+        };
+        if let Some(root) = self.track_tree.root() {
+            tlm.root = self.list_for_item(&root);
+        }
+        tlm
+    }
+
+    pub fn list_for_item(&self, item: &TreeItem) -> List {
+        let mut list = List::new(&item.label().unwrap_or_default());
+        for i in 0..item.children() {
+            if let Some(child) = item.child(i) {
+                match unsafe { child.user_data::<TrackID>() } {
+                    Some(tid) => {
+                        if self.track_for_tid.contains_key(&tid) {
+                            list.push_track(tid);
+                        }
+                    }
+                    None => list.push_list(self.list_for_item(&child)),
+                }
+            }
+        }
+        list
+    }
+
     pub fn save_as(&mut self, filename: &Path) -> Result<()> {
         self.filename = filename.to_path_buf();
+        self.version = FORMAT_VERSION; // a copy, or overwrite confirmed
         self.save()
     }
 
+    // Returns the tracks whose durations can be read from their files
+    // (i.e., not virtual tracks with a known end), and when the .tlm file
+    // was last saved (if it has been)
+    // Returns jobs for all the tracks or only for the given ones
+    pub fn duration_jobs(
+        &self,
+        only: Option<&[TrackID]>,
+    ) -> (Vec<Job>, Option<SystemTime>) {
+        let jobs = self
+            .track_for_tid
+            .iter()
+            .filter(|(tid, track)| {
+                track.end <= 0.0
+                    && only.is_none_or(|tids| tids.contains(tid))
+            })
+            .map(|(tid, track)| Job {
+                tid: *tid,
+                filename: track.filename.clone(),
+                secs: track.secs,
+                start: track.start,
+            })
+            .collect();
+        let saved =
+            fs::metadata(&self.filename).and_then(|m| m.modified()).ok();
+        (jobs, saved)
+    }
+
+    // Updates the tracks' durations and their tree icons
+    pub fn set_durations(&mut self, durations: &[(TrackID, f64)]) {
+        let mut secs_for_tid = HashMap::new();
+        for (tid, secs) in durations {
+            if let Some(track) = self.track_for_tid.get_mut(tid) {
+                if !util::same_secs(track.secs, *secs) {
+                    track.secs = *secs;
+                    secs_for_tid.insert(*tid, *secs);
+                }
+            }
+        }
+        if secs_for_tid.is_empty() {
+            return;
+        }
+        self.set_dirty();
+        let mut opt_item = self.track_tree.first();
+        while let Some(mut item) = opt_item {
+            if let Some(tid) = unsafe { item.user_data::<TrackID>() } {
+                if let Some(secs) = secs_for_tid.get(&tid) {
+                    item.set_user_icon(Some(image_for_secs(*secs)));
+                }
+            }
+            opt_item = item.next();
+        }
+        self.track_tree.redraw();
+    }
+
     pub fn track_for_item(&self, item: &TreeItem) -> Option<Track> {
         let tid = unsafe { item.user_data::<TrackID>() };
         if let Some(tid) = tid {
@@ -398,14 +603,3 @@ fn image_for_secs(secs: f64) -> SvgImage {
     let icons = TIME_ICONS.get().read().unwrap();
     icons[index].clone()
 }
-
-const INDENT: char = '\x0B';
-const TAB: char = '\x09';
-
-#[derive(PartialEq)]
-enum State {
-    WantMagic,
-    WantTrackHeader,
-    InTracks,
-    InHistory,
-}
@@ -4,6 +4,7 @@
 use crate::fixed::{
     APPNAME, BUTTON_HEIGHT, BUTTON_WIDTH, ICON, PAD, TOP_LEVEL_NAME,
 };
+use crate::folder_import::Order;
 use crate::util;
 use fltk::{
     app,
@@ -31,6 +32,10 @@ pub struct Form {
     pub parent_list: Rc<RefCell<String>>,
     pub folder_or_playlist: Rc<RefCell<PathBuf>>,
     pub include_subdirs: Rc<RefCell<bool>>,
+    pub nested_subdirs: Rc<RefCell<bool>>,
+    pub order: Rc<RefCell<Order>>,
+    pub include: Rc<RefCell<String>>,
+    pub exclude: Rc<RefCell<String>>,
 }
 
 impl Form {
@@ -40,6 +45,10 @@ impl Form {
         let parent_list = Rc::from(RefCell::from(String::new()));
         let folder_or_playlist = Rc::from(RefCell::from(PathBuf::new()));
         let include_subdirs = Rc::from(RefCell::from(false));
+        let nested_subdirs = Rc::from(RefCell::from(false));
+        let order = Rc::from(RefCell::from(Order::FileName));
+        let include = Rc::from(RefCell::from(String::new()));
+        let exclude = Rc::from(RefCell::from(String::new()));
         let mut form = make_form();
         let mut vbox = Flex::default().size_of_parent().column();
         vbox.set_margin(PAD);
@@ -60,6 +69,10 @@ impl Form {
             Rc::clone(&parent_list),
             Rc::clone(&folder_or_playlist),
             Rc::clone(&include_subdirs),
+            Rc::clone(&nested_subdirs),
+            Rc::clone(&order),
+            Rc::clone(&include),
+            Rc::clone(&exclude),
         );
         widgets.parent_list_combo.take_focus().unwrap();
         let mut ui_widgets = UiWidgets {
@@ -80,6 +93,10 @@ impl Form {
             parent_list,
             folder_or_playlist,
             include_subdirs,
+            nested_subdirs,
+            order,
+            include,
+            exclude,
         }
     }
 }
@@ -99,6 +116,13 @@ struct Widgets {
     pub choose_button: Button,
     pub folder_or_playlist_label: Frame,
     pub include_subdirs_checkbox: CheckButton,
+    pub nested_subdirs_checkbox: CheckButton,
+    pub order_button: Button,
+    pub order_combo: Choice,
+    pub include_button: Button,
+    pub include_input: Input,
+    pub exclude_button: Button,
+    pub exclude_input: Input,
     pub name_button: Button,
     pub name_input: Input,
 }
@@ -133,7 +157,8 @@ fn make_widgets(top_levels: &[String]) -> Widgets {
     let mut parent_list_combo = Choice::default();
     parent_list_combo.add_choice(TOP_LEVEL_NAME);
     for top_level in top_levels {
-        parent_list_combo.add_choice(top_level);
+        // Menus use '/' for submenus and escape it just like tree paths
+        parent_list_combo.add_choice(&util::treepath_escaped(top_level));
     }
     parent_list_combo.set_value(0);
     row1.set_size(&parent_list_button, width);
@@ -160,24 +185,49 @@ fn make_widgets(top_levels: &[String]) -> Widgets {
     let include_subdirs_checkbox =
         CheckButton::default().with_label("Include &Subfolders");
     include_subdirs_checkbox.set_checked(true);
+    let nested_subdirs_checkbox =
+        CheckButton::default().with_label("Subfolders as Su&blists");
+    let mut order_button = Button::default().with_label("O&rder");
+    order_button.set_frame(FrameType::NoBox);
+    order_button.visible_focus(false);
+    let mut order_combo = Choice::default();
+    order_combo.add_choice("Filename|Natural|Disc && Track Number");
+    order_combo.set_value(0);
     row4.set_size(&include_subdirs_checkbox, width);
+    row4.set_size(&order_button, BUTTON_WIDTH);
     row4.end();
     let mut row5 = Flex::default().row();
     row5.set_pad(PAD);
+    let mut include_button = Button::default().with_label("Incl&ude");
+    include_button.set_frame(FrameType::NoBox);
+    include_button.visible_focus(false);
+    include_button.set_tooltip("e.g., *.flac; *.ogg");
+    let include_input = Input::default();
+    let mut exclude_button = Button::default().with_label("E&xclude");
+    exclude_button.set_frame(FrameType::NoBox);
+    exclude_button.visible_focus(false);
+    exclude_button.set_tooltip("e.g., *live*; *demo*");
+    let exclude_input = Input::default();
+    row5.set_size(&include_button, width);
+    row5.set_size(&exclude_button, BUTTON_WIDTH);
+    row5.end();
+    let mut row6 = Flex::default().row();
+    row6.set_pad(PAD);
     let mut name_button = Button::default().with_label("&Name");
     name_button.set_frame(FrameType::NoBox);
     name_button.visible_focus(false);
     let mut name_input = Input::default();
     name_input
         .set_trigger(CallbackTrigger::Changed | CallbackTrigger::EnterKey);
-    row5.set_size(&name_button, width);
-    row5.end();
+    row6.set_size(&name_button, width);
+    row6.end();
     column.end();
     column.set_size(&row1, BUTTON_HEIGHT);
     column.set_size(&row2, BUTTON_HEIGHT);
     column.set_size(&row3, BUTTON_HEIGHT);
     column.set_size(&row4, BUTTON_HEIGHT);
     column.set_size(&row5, BUTTON_HEIGHT);
+    column.set_size(&row6, BUTTON_HEIGHT);
     Widgets {
         parent_list_button,
         parent_list_combo,
@@ -187,6 +237,13 @@ fn make_widgets(top_levels: &[String]) -> Widgets {
         choose_button,
         folder_or_playlist_label,
         include_subdirs_checkbox,
+        nested_subdirs_checkbox,
+        order_button,
+        order_combo,
+        include_button,
+        include_input,
+        exclude_button,
+        exclude_input,
         name_button,
         name_input,
     }
@@ -224,6 +281,10 @@ fn add_event_handlers(
     parent_list: Rc<RefCell<String>>,
     folder_or_playlist: Rc<RefCell<PathBuf>>,
     include_subdirs: Rc<RefCell<bool>>,
+    nested_subdirs: Rc<RefCell<bool>>,
+    order: Rc<RefCell<Order>>,
+    include: Rc<RefCell<String>>,
+    exclude: Rc<RefCell<String>>,
 ) {
     let mut ui_widgets = UiWidgets {
         empty_radio: widgets.empty_radio.clone(),
@@ -237,6 +298,24 @@ fn add_event_handlers(
             parent_list_combo.take_focus().unwrap();
         }
     });
+    widgets.order_button.set_callback({
+        let mut order_combo = widgets.order_combo.clone();
+        move |_| {
+            order_combo.take_focus().unwrap();
+        }
+    });
+    widgets.include_button.set_callback({
+        let mut include_input = widgets.include_input.clone();
+        move |_| {
+            include_input.take_focus().unwrap();
+        }
+    });
+    widgets.exclude_button.set_callback({
+        let mut exclude_input = widgets.exclude_input.clone();
+        move |_| {
+            exclude_input.take_focus().unwrap();
+        }
+    });
     widgets.name_button.set_callback({
         let mut name_input = widgets.name_input.clone();
         move |_| {
@@ -273,6 +352,17 @@ fn add_event_handlers(
             update_ui(&mut ui_widgets);
         }
     };
+    widgets.include_subdirs_checkbox.set_callback({
+        let mut nested_subdirs_checkbox =
+            widgets.nested_subdirs_checkbox.clone();
+        move |include_subdirs_checkbox| {
+            if include_subdirs_checkbox.is_checked() {
+                nested_subdirs_checkbox.activate();
+            } else {
+                nested_subdirs_checkbox.deactivate();
+            }
+        }
+    });
     widgets.folder_radio.set_callback(callback.clone());
     widgets.playlist_radio.set_callback(callback.clone());
     widgets.empty_radio.set_callback(callback);
@@ -289,6 +379,11 @@ fn add_event_handlers(
             widgets.folder_or_playlist_label.clone();
         let include_subdirs_checkbox =
             widgets.include_subdirs_checkbox.clone();
+        let nested_subdirs_checkbox =
+            widgets.nested_subdirs_checkbox.clone();
+        let order_combo = widgets.order_combo.clone();
+        let include_input = widgets.include_input.clone();
+        let exclude_input = widgets.exclude_input.clone();
         move |_| {
             *ok.borrow_mut() = true;
             *name.borrow_mut() = name_input.value();
@@ -298,6 +393,16 @@ fn add_event_handlers(
                 PathBuf::from(folder_or_playlist_label.label());
             *include_subdirs.borrow_mut() =
                 include_subdirs_checkbox.is_checked();
+            *nested_subdirs.borrow_mut() = include_subdirs_checkbox
+                .is_checked()
+                && nested_subdirs_checkbox.is_checked();
+            *order.borrow_mut() = match order_combo.value() {
+                1 => Order::Natural,
+                2 => Order::Tags,
+                _ => Order::FileName,
+            };
+            *include.borrow_mut() = include_input.value();
+            *exclude.borrow_mut() = exclude_input.value();
             form.hide();
         }
     });
@@ -339,7 +444,7 @@ fn playlist_filename(current_track: PathBuf) -> String {
     let mut form = FileDialog::new(FileDialogType::BrowseFile);
     form.set_title(&format!("Choose Playlist — {APPNAME}"));
     let _ = form.set_directory(&util::get_track_dir(&current_track));
-    form.set_filter("Playlists\t*.{m3u,pls,xspf}");
+    form.set_filter("Playlists\t*.{cue,m3u,m3u8,pls,xspf}");
     form.show();
     let filename = form.filename();
     if filename.exists() {
@@ -350,4 +455,4 @@ fn playlist_filename(current_track: PathBuf) -> String {
 }
 
 const WIDTH: i32 = 640;
-const HEIGHT: i32 = PAD + ((BUTTON_HEIGHT + PAD) * 6);
+const HEIGHT: i32 = PAD + ((BUTTON_HEIGHT + PAD) * 7);
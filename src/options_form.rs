@@ -2,9 +2,11 @@
 // License: GPLv3
 
 use super::CONFIG;
+use crate::config;
 use crate::fixed::{
-    APPNAME, BUTTON_HEIGHT, BUTTON_WIDTH, ICON, MAX_HISTORY_SIZE,
-    MIN_HISTORY_SIZE, PAD, SCALE_MAX, SCALE_MIN,
+    APPNAME, BUTTON_HEIGHT, BUTTON_WIDTH, DEFAULT_BACKUPS, ICON,
+    MAX_BACKUPS, MAX_HISTORY_SIZE, MIN_HISTORY_SIZE, PAD, SCALE_MAX,
+    SCALE_MIN,
 };
 use crate::util;
 use fltk::{
@@ -14,6 +16,7 @@ use fltk::{
     frame::Frame,
     group::Flex,
     image::SvgImage,
+    input::MultilineInput,
     misc::Spinner,
     prelude::*,
     window::Window,
@@ -34,7 +37,7 @@ impl Form {
         vbox.set_margin(PAD);
         vbox.set_pad(PAD);
         make_config_row();
-        let mut widgets = make_widgets();
+        let mut widgets = make_widgets(&mut vbox);
         let (button_row, mut buttons) = make_buttons();
         vbox.set_size(&button_row, BUTTON_HEIGHT);
         vbox.end();
@@ -63,7 +66,12 @@ impl Drop for Form {
 
 struct Widgets {
     pub history_size_spinner: Spinner,
+    pub backups_spinner: Spinner,
     pub auto_save_checkbox: CheckButton,
+    pub live_reload_checkbox: CheckButton,
+    pub relative_paths_checkbox: CheckButton,
+    pub music_roots_input: MultilineInput,
+    pub path_remaps_input: MultilineInput,
     pub scale_spinner: Spinner,
 }
 
@@ -98,7 +106,7 @@ fn make_config_row() {
     row.end();
 }
 
-fn make_widgets() -> Widgets {
+fn make_widgets(vbox: &mut Flex) -> Widgets {
     let config = CONFIG.get().read().unwrap();
     let history_size_spinner = make_row(
         "&History Size",
@@ -108,6 +116,14 @@ fn make_widgets() -> Widgets {
         MAX_HISTORY_SIZE as f64,
         1.0,
     );
+    let backups_spinner = make_row(
+        "&Backups",
+        config.backups as f64,
+        &format!("The number of timestamped backups to keep of each .tlm file (default {DEFAULT_BACKUPS}; 0 means none)"),
+        0.0,
+        MAX_BACKUPS as f64,
+        1.0,
+    );
     let mut row = Flex::default().row();
     row.set_pad(PAD);
     Frame::default().with_size(PAD, PAD);
@@ -116,6 +132,44 @@ fn make_widgets() -> Widgets {
         .with_align(Align::Inside | Align::Left);
     auto_save_checkbox.set_checked(config.auto_save);
     row.end();
+    let mut row = Flex::default().row();
+    row.set_pad(PAD);
+    Frame::default().with_size(PAD, PAD);
+    let mut live_reload_checkbox = CheckButton::default()
+        .with_label("&Live Reload")
+        .with_align(Align::Inside | Align::Left);
+    live_reload_checkbox.set_checked(config.live_reload);
+    live_reload_checkbox.set_tooltip(
+        "Reload the .tlm file if it is changed on disk while there are no \
+        unsaved changes",
+    );
+    row.end();
+    let mut row = Flex::default().row();
+    row.set_pad(PAD);
+    Frame::default().with_size(PAD, PAD);
+    let mut relative_paths_checkbox = CheckButton::default()
+        .with_label("&Relative Track Paths")
+        .with_align(Align::Inside | Align::Left);
+    relative_paths_checkbox.set_checked(config.relative_paths);
+    relative_paths_checkbox.set_tooltip(
+        "Save track paths relative to the .tlm file where possible",
+    );
+    row.end();
+    let (row, music_roots_input) = make_input_row(
+        "Music R&oots",
+        &config::music_roots_to_text(&config.music_roots),
+        "Named folders that track paths are saved relative to, e.g., \
+        MUSIC=/home/me/Music; put each on its own line",
+    );
+    vbox.set_size(&row, INPUT_HEIGHT);
+    let (row, path_remaps_input) = make_input_row(
+        "&Path Remaps",
+        &config::path_remaps_to_text(&config.path_remaps),
+        "Folders as saved in shared .tlm files and where they are on this \
+        machine, e.g., /home/a/Music=/mnt/nas/music; put each on its own \
+        line",
+    );
+    vbox.set_size(&row, INPUT_HEIGHT);
     let scale_spinner = make_row(
         "&Scale",
         config.window_scale as f64,
@@ -124,7 +178,42 @@ fn make_widgets() -> Widgets {
         SCALE_MAX as f64,
         0.1,
     );
-    Widgets { history_size_spinner, auto_save_checkbox, scale_spinner }
+    Widgets {
+        history_size_spinner,
+        backups_spinner,
+        auto_save_checkbox,
+        live_reload_checkbox,
+        relative_paths_checkbox,
+        music_roots_input,
+        path_remaps_input,
+        scale_spinner,
+    }
+}
+
+fn make_input_row(
+    label: &str,
+    value: &str,
+    tooltip: &str,
+) -> (Flex, MultilineInput) {
+    let mut row = Flex::default().row();
+    row.set_pad(PAD);
+    let mut label = Button::default()
+        .with_label(label)
+        .with_align(Align::Inside | Align::Left);
+    label.set_frame(FrameType::NoBox);
+    label.clear_visible_focus();
+    let mut input = MultilineInput::default();
+    input.set_value(value);
+    input.set_tooltip(tooltip);
+    row.set_size(&label, WIDTH / 3);
+    row.end();
+    label.set_callback({
+        let mut input = input.clone();
+        move |_| {
+            input.take_focus().unwrap();
+        }
+    });
+    (row, input)
 }
 
 fn make_row(
@@ -179,7 +268,13 @@ fn add_event_handlers(
 ) {
     buttons.ok_button.set_callback({
         let auto_save_checkbox = widgets.auto_save_checkbox.clone();
+        let live_reload_checkbox = widgets.live_reload_checkbox.clone();
+        let relative_paths_checkbox =
+            widgets.relative_paths_checkbox.clone();
+        let music_roots_input = widgets.music_roots_input.clone();
+        let path_remaps_input = widgets.path_remaps_input.clone();
         let history_size_spinner = widgets.history_size_spinner.clone();
+        let backups_spinner = widgets.backups_spinner.clone();
         let scale_spinner = widgets.scale_spinner.clone();
         let mut form = form.clone();
         move |_| {
@@ -191,7 +286,14 @@ fn add_event_handlers(
                 app::set_screen_scale(0, scale);
             }
             config.history_size = history_size_spinner.value() as usize;
+            config.backups = backups_spinner.value() as usize;
             config.auto_save = auto_save_checkbox.is_checked();
+            config.live_reload = live_reload_checkbox.is_checked();
+            config.relative_paths = relative_paths_checkbox.is_checked();
+            config.music_roots =
+                config::music_roots_from_text(&music_roots_input.value());
+            config.path_remaps =
+                config::path_remaps_from_text(&path_remaps_input.value());
             form.hide();
         }
     });
@@ -204,4 +306,5 @@ fn add_event_handlers(
 }
 
 const WIDTH: i32 = 340;
-const HEIGHT: i32 = 150;
+const HEIGHT: i32 = 370;
+const INPUT_HEIGHT: i32 = BUTTON_HEIGHT * 2; // room for a few lines
@@ -0,0 +1,83 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::fixed::{
+    Action, APPNAME, BUTTON_HEIGHT, BUTTON_WIDTH, ICON, PAD,
+};
+use fltk::{
+    app::{self, Sender},
+    button::Button,
+    enums::Align,
+    frame::Frame,
+    group::Flex,
+    image::SvgImage,
+    prelude::*,
+    window::Window,
+};
+
+// A non-modal window that shows how many tracks an import has added so
+// far; cancelling (or closing the window) sends Action::ImportCancel
+pub struct Form {
+    form: Window,
+    count_label: Frame,
+}
+
+impl Form {
+    pub fn new(title: &str, sender: Sender<Action>) -> Self {
+        let mut form = make_form(title);
+        let mut vbox = Flex::default().size_of_parent().column();
+        vbox.set_margin(PAD);
+        vbox.set_pad(PAD);
+        let count_label = Frame::default()
+            .with_label("Importing…")
+            .with_align(Align::Inside | Align::Left);
+        let (button_row, mut cancel_button) = make_buttons();
+        vbox.set_size(&button_row, BUTTON_HEIGHT);
+        vbox.end();
+        form.end();
+        cancel_button.set_callback(move |_| {
+            sender.send(Action::ImportCancel);
+        });
+        form.set_callback(move |_| {
+            sender.send(Action::ImportCancel);
+        });
+        form.show();
+        Self { form, count_label }
+    }
+
+    pub fn set_count(&mut self, count: usize) {
+        self.count_label.set_label(&format!("Added {count} tracks…"));
+    }
+}
+
+impl Drop for Form {
+    fn drop(&mut self) {
+        app::delete_widget(self.form.clone());
+    }
+}
+
+fn make_form(title: &str) -> Window {
+    let image = SvgImage::from_data(ICON).unwrap();
+    let mut form = Window::default()
+        .with_size(WIDTH, HEIGHT)
+        .with_label(&format!("{title} — {APPNAME}"));
+    if let Some(window) = app::first_window() {
+        form.set_pos(window.x() + 50, window.y() + 100);
+    }
+    form.set_icon(Some(image));
+    form
+}
+
+fn make_buttons() -> (Flex, Button) {
+    let mut row = Flex::default().size_of_parent().row();
+    row.set_pad(PAD);
+    Frame::default(); // pad left of button
+    let cancel_button = Button::default().with_label("&Cancel");
+    Frame::default(); // pad right of button
+    row.set_size(&cancel_button, BUTTON_WIDTH);
+    row.end();
+    (row, cancel_button)
+}
+
+const WIDTH: i32 = 300;
+const HEIGHT: i32 = PAD + ((BUTTON_HEIGHT + PAD) * 2);
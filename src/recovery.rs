@@ -0,0 +1,83 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use super::CONFIG;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
+use tlmlib::Tlm;
+
+// Writes recovery files in a worker thread so that compressing a large
+// file doesn't hold up the GUI; written is the model generation last
+// written so that unchanged models aren't written again
+#[derive(Default)]
+pub struct Writer {
+    written: u64,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Writer {
+    // Returns true if the model has changed since it was last written and
+    // the previous write (if any) has finished
+    pub fn is_due(&self, generation: u64) -> bool {
+        generation != self.written
+            && self
+                .handle
+                .as_ref()
+                .is_none_or(|handle| handle.is_finished())
+    }
+
+    pub fn write(&mut self, tlm: Tlm, generation: u64) {
+        let recovery = filename_for(&tlm.filename);
+        self.written = generation;
+        self.handle = Some(thread::spawn(move || {
+            let _ = tlm.save(&recovery); // Ignore error
+        }));
+    }
+
+    // Deletes the .tlm file's recovery file (if any) once its changes have
+    // been saved or discarded, first waiting for any write in progress so
+    // that it can't recreate it
+    pub fn remove(&mut self, filename: &Path) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join(); // Ignore error
+        }
+        let _ = fs::remove_file(filename_for(filename)); // Ignore error
+    }
+}
+
+// Returns the crash recovery file for the .tlm file, e.g.,
+// .music.tlm.recovery next to it (so that its relative track paths stay
+// valid), or one next to the configuration file if it is untitled
+pub fn filename_for(filename: &Path) -> PathBuf {
+    if filename.as_os_str().is_empty() {
+        let config = CONFIG.get().read().unwrap();
+        return config.filename.with_extension(SUFFIX);
+    }
+    let mut name = OsString::from(".");
+    name.push(filename.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(SUFFIX);
+    filename.with_file_name(name)
+}
+
+// Returns the recovery file if there is one that is newer than the .tlm
+// file, i.e., one left by a crash; an older one is stale (e.g., the file
+// was saved elsewhere since) so is deleted
+pub fn find(filename: &Path) -> Option<PathBuf> {
+    let recovery = filename_for(filename);
+    let recovered =
+        fs::metadata(&recovery).and_then(|m| m.modified()).ok()?;
+    match fs::metadata(filename).and_then(|m| m.modified()) {
+        Ok(saved) if saved >= recovered => {
+            let _ = fs::remove_file(&recovery); // Ignore error
+            None
+        }
+        _ => Some(recovery), // e.g., the file is untitled
+    }
+}
+
+const SUFFIX: &str = "recovery";
@@ -0,0 +1,208 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::fixed::Action;
+use crate::util;
+use fltk::app::Sender;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+use walkdir::WalkDir;
+
+// A track file that doesn't exist; secs is 0 if its duration is unknown
+// (e.g., for virtual tracks, whose secs are only part of the file)
+pub struct Missing {
+    pub filename: PathBuf,
+    pub secs: f64,
+}
+
+// Searches folders in a worker thread for files that match missing track
+// files by filename, or failing that, by title tag, and by duration; when
+// done the (missing, found) pairs are sent and the GUI is told with
+// Action::MissingTracksSearched
+pub struct Search {
+    pub receiver: mpsc::Receiver<Vec<(PathBuf, PathBuf)>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Search {
+    pub fn new(
+        missing: Vec<Missing>,
+        folders: Vec<PathBuf>,
+        sender: Sender<Action>,
+    ) -> Self {
+        let (results, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let cancelled = Arc::clone(&cancelled);
+            move || {
+                if let Some(found) = search(&missing, &folders, &cancelled)
+                {
+                    if results.send(found).is_ok() {
+                        sender.send(Action::MissingTracksSearched);
+                    }
+                }
+            }
+        });
+        Self { receiver, cancelled }
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Returns the (missing, found) pairs, or None if cancelled
+fn search(
+    missing: &[Missing],
+    folders: &[PathBuf],
+    cancelled: &AtomicBool,
+) -> Option<Vec<(PathBuf, PathBuf)>> {
+    let mut candidates = Candidates::default();
+    for folder in folders {
+        for entry in WalkDir::new(folder).into_iter().filter_map(|e| e.ok())
+        {
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+            if entry.file_type().is_file()
+                && util::is_audio_file(entry.path())
+            {
+                candidates.add(entry.into_path());
+            }
+        }
+    }
+    let mut found = vec![];
+    for track in missing {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let by_name = candidates.with_same_name(&track.filename);
+        let matched = match best_match(track, &by_name, false) {
+            Some(path) => Some(path),
+            None if track.secs > 0.0 => {
+                // The file may have been renamed, so try its title but
+                // only accept it if the duration matches too
+                let by_title = candidates.with_title(&track.filename);
+                best_match(track, &by_title, true)
+            }
+            None => None,
+        };
+        if let Some(path) = matched {
+            found.push((track.filename.clone(), path));
+        }
+    }
+    Some(found)
+}
+
+// Returns the candidate whose duration is closest to the missing track's
+// (within DURATION_TOLERANCE) preferring those whose title tag matches the
+// track's name; if the track's duration is unknown or a candidate's can't
+// be read, the duration is only required to match if must_match_secs
+fn best_match(
+    track: &Missing,
+    candidates: &[PathBuf],
+    must_match_secs: bool,
+) -> Option<PathBuf> {
+    let name = key_for(&util::canonicalize(&track.filename));
+    let mut best: Option<((bool, f64), &PathBuf)> = None;
+    for candidate in candidates {
+        let difference = if track.secs > 0.0 {
+            match util::get_track_secs(candidate) {
+                Ok(secs) => (secs - track.secs).abs(),
+                Err(_) if !must_match_secs => DURATION_TOLERANCE,
+                Err(_) => continue,
+            }
+        } else if must_match_secs {
+            continue;
+        } else {
+            0.0
+        };
+        if difference > DURATION_TOLERANCE {
+            continue;
+        }
+        let same_title = title_key(candidate).as_ref() == Some(&name);
+        let score = (same_title, -difference);
+        match best {
+            Some((best_score, _)) if best_score >= score => {}
+            _ => best = Some((score, candidate)),
+        }
+    }
+    best.map(|(_, candidate)| candidate.clone())
+}
+
+// The audio files in the searched folders indexed by (case-insensitive)
+// filename, and, only if needed, by title tag
+#[derive(Default)]
+struct Candidates {
+    paths: Vec<PathBuf>,
+    for_name: HashMap<String, Vec<usize>>,
+    for_title: Option<HashMap<String, Vec<usize>>>,
+}
+
+impl Candidates {
+    fn add(&mut self, path: PathBuf) {
+        if let Some(name) = path.file_name() {
+            self.for_name
+                .entry(key_for(&name.to_string_lossy()))
+                .or_default()
+                .push(self.paths.len());
+        }
+        self.paths.push(path);
+    }
+
+    fn with_same_name(&self, filename: &Path) -> Vec<PathBuf> {
+        let name = filename
+            .file_name()
+            .map(|name| key_for(&name.to_string_lossy()))
+            .unwrap_or_default();
+        self.paths_for(self.for_name.get(&name))
+    }
+
+    // Reads every candidate's title tag the first time it is called
+    fn with_title(&mut self, filename: &Path) -> Vec<PathBuf> {
+        if self.for_title.is_none() {
+            let mut for_title: HashMap<String, Vec<usize>> = HashMap::new();
+            for (i, path) in self.paths.iter().enumerate() {
+                if let Some(title) = title_key(path) {
+                    for_title.entry(title).or_default().push(i);
+                }
+            }
+            self.for_title = Some(for_title);
+        }
+        let name = key_for(&util::canonicalize(filename));
+        let indexes = self.for_title.as_ref().and_then(|t| t.get(&name));
+        self.paths_for(indexes)
+    }
+
+    fn paths_for(&self, indexes: Option<&Vec<usize>>) -> Vec<PathBuf> {
+        indexes
+            .map(|indexes| {
+                indexes.iter().map(|i| self.paths[*i].clone()).collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn title_key(path: &Path) -> Option<String> {
+    match util::get_track_tag(path) {
+        Ok(Some(data)) if !data.title.trim().is_empty() => {
+            Some(key_for(&data.title))
+        }
+        _ => None,
+    }
+}
+
+fn key_for(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+const DURATION_TOLERANCE: f64 = 2.0; // seconds
@@ -2,16 +2,19 @@
 // License: GPLv3
 
 use crate::application::Application;
+use crate::duration_scan;
 use crate::fixed::{
-    Action, APPNAME, PATH_SEP, PAUSE_ICON, PLAY_ICON, TINY_TIMEOUT,
-    TOOLBUTTON_SIZE,
+    Action, APPNAME, AUDIO_SUFFIXES, INFO_TIMEOUT, PAUSE_ICON, PLAY_ICON,
+    TINY_TIMEOUT, TOOLBUTTON_SIZE,
 };
+use crate::html_form;
 use crate::list_form::{self, Reply};
-use crate::model::Track;
+use crate::model::{Track, TrackID};
+use crate::relink;
 use crate::util;
 use fltk::{
     app,
-    dialog::{FileDialog, FileDialogType},
+    dialog::{self, FileDialog, FileDialogType},
     image::SvgImage,
     prelude::*,
 };
@@ -41,21 +44,124 @@ impl Application {
         let mut form = FileDialog::new(FileDialogType::BrowseFile);
         form.set_title(&format!("Add Track — {APPNAME}"));
         let _ = form.set_directory(&folder.unwrap());
-        form.set_filter("Audio Files\t*.{flac,mogg,mp3,oga,ogg,wav}");
+        form.set_filter(&format!(
+            "Audio Files\t*.{{{}}}",
+            AUDIO_SUFFIXES.join(",")
+        ));
         form.show();
         let track = form.filename();
-        if track.exists()
-            && self
-                .tlm
-                .add_track(
-                    &util::treepath_for_item(parent_item),
-                    Track::new(track, 0.0),
-                )
-                .is_some()
-        {
+        if !track.exists() {
+            return;
+        }
+        if let Some((_, item)) = self.tlm.add_track(
+            &util::treepath_for_item(parent_item),
+            Track::new(track, 0.0),
+        ) {
             self.tlm.track_tree.redraw();
             self.update_ui();
+            if let Some(tid) = unsafe { item.user_data::<TrackID>() } {
+                self.scan_added_durations(&[tid]);
+            }
+        }
+    }
+
+    pub(crate) fn on_track_check(&mut self) {
+        if self.relink_search.is_some() {
+            util::popup_error_message("Still searching for missing tracks");
+            return;
+        }
+        let missing = self.tlm.missing_tracks();
+        if missing.is_empty() {
+            self.info_view.set_value(&format!(
+                "<font color=green>All {} tracks' files exist</font>",
+                self.tlm.track_for_tid.len()
+            ));
+            self.clear_info_after(INFO_TIMEOUT);
+            return;
+        }
+        let mut html = format!(
+            "<p><font color=red>{} track files are missing:</font></p><ul>",
+            missing.len()
+        );
+        for track in &missing {
+            html.push_str(&format!(
+                "<li>{}</li>",
+                util::html_escaped(&track.filename.to_string_lossy())
+            ));
+        }
+        html.push_str("</ul>");
+        html_form::Form::new("Check Tracks", &html, true, 500, 400, true);
+        dialog::message_title(&format!("Check Tracks — {APPNAME}"));
+        if dialog::choice2_default(
+            "Search folders for the missing track files?",
+            "&Cancel",
+            "&Search…",
+            "",
+        ) != Some(1)
+        {
+            return;
         }
+        let folders = choose_folders_to_search();
+        if !folders.is_empty() {
+            self.relink_search =
+                Some(relink::Search::new(missing, folders, self.sender));
+            self.info_view.set_value("Searching for missing tracks…");
+        }
+    }
+
+    pub(crate) fn on_missing_tracks_searched(&mut self) {
+        let found = match &self.relink_search {
+            Some(search) => match search.receiver.try_recv() {
+                Ok(found) => found,
+                Err(_) => return,
+            },
+            None => return,
+        };
+        self.relink_search = None;
+        self.info_view.set_value("");
+        let missing = self.tlm.missing_tracks().len();
+        if found.is_empty() {
+            util::popup_error_message(&format!(
+                "None of the {missing} missing track files were found"
+            ));
+            return;
+        }
+        let mut html = format!(
+            "<p><font color=green>Found {} of {missing} missing track \
+            files:</font></p><ul>",
+            found.len()
+        );
+        for (old, new) in &found {
+            html.push_str(&format!(
+                "<li>{}<br>→ <font color=navy>{}</font></li>",
+                util::html_escaped(&old.to_string_lossy()),
+                util::html_escaped(&new.to_string_lossy())
+            ));
+        }
+        html.push_str("</ul>");
+        html_form::Form::new("Check Tracks", &html, true, 500, 400, true);
+        dialog::message_title(&format!("Check Tracks — {APPNAME}"));
+        if dialog::choice2_default(
+            "Relink the tracks to the files found?",
+            "&Cancel",
+            "&Relink",
+            "",
+        ) != Some(1)
+        {
+            return;
+        }
+        let count = self.tlm.relink(&found);
+        if let Some((_, new)) =
+            found.iter().find(|(old, _)| *old == self.current.track)
+        {
+            self.current.track = new.clone();
+        }
+        self.tlm.track_tree.redraw();
+        self.update_ui();
+        self.scan_durations();
+        self.info_view
+            .set_value(&format!("Relinked {count} tracks to new files"));
+        self.clear_info_after(INFO_TIMEOUT);
     }
 
     pub(crate) fn on_track_previous(&mut self) {
@@ -112,8 +218,9 @@ impl Application {
 
     fn play_history_track(&mut self, index: i32) {
         if index > -1 {
-            if let Some(treepath) = self.history_menu_button.text(index) {
-                let treepath = treepath[3..].replace(PATH_SEP, "/");
+            if let Some(treepath) =
+                self.tlm.history_iter().nth(index as usize).cloned()
+            {
                 if let Some(mut item) =
                     self.tlm.track_tree.first_selected_item()
                 {
@@ -131,7 +238,7 @@ impl Application {
         let list = {
             let mut list = vec![];
             for treepath in self.tlm.history_iter() {
-                list.push(treepath.clone());
+                list.push(util::treepath_display(treepath));
             }
             list
         };
@@ -187,7 +294,11 @@ impl Application {
                     self.handle,
                     self.volume_slider.value() as f32,
                 );
-                let secs = self.wav.length();
+                if self.current.start > 0.0 {
+                    let _ =
+                        self.player.seek(self.handle, self.current.start);
+                }
+                let secs = self.track_length();
                 self.time_slider.set_range(0.0, secs);
                 self.time_slider.set_step(secs, 20);
                 self.time_slider.set_value(0.0);
@@ -219,9 +330,11 @@ impl Application {
         app::redraw(); // redraws the world
     }
 
+    // pos is relative to the start of the current (possibly virtual) track
     pub(crate) fn seek(&mut self, pos: f64) {
-        if self.player.seek(self.handle, pos).is_ok() {
-            while self.player.stream_position(self.handle) < pos {
+        let stream_pos = self.current.start + pos;
+        if self.player.seek(self.handle, stream_pos).is_ok() {
+            while self.player.stream_position(self.handle) < stream_pos {
                 thread::sleep(Duration::from_millis(100));
             }
         }
@@ -229,8 +342,87 @@ impl Application {
         self.time_label.set_label(&format!(
             "{}/{}",
             util::humanized_time(pos),
-            util::humanized_time(self.wav.length())
+            util::humanized_time(self.track_length())
         ));
         app::redraw(); // redraws the world
     }
+
+    // Returns the length of the current track, which for a virtual track
+    // is the length of its part of the file
+    pub(crate) fn track_length(&self) -> f64 {
+        let length = self.wav.length();
+        let end = if self.current.end > 0.0 {
+            self.current.end.min(length)
+        } else {
+            length
+        };
+        (end - self.current.start).max(0.0)
+    }
+
+    // Starts reading the durations of any tracks whose secs are 0 or
+    // stale, replacing (and so cancelling) any scan already in progress
+    pub(crate) fn scan_durations(&mut self) {
+        let (jobs, saved) = self.tlm.duration_jobs(None);
+        self.duration_scan = if jobs.is_empty() {
+            None
+        } else {
+            Some(duration_scan::Scan::new(jobs, saved, self.sender))
+        };
+    }
+
+    // Only the added tracks are scanned, by the running scan if there is
+    // one
+    pub(crate) fn scan_added_durations(&mut self, tids: &[TrackID]) {
+        let (jobs, saved) = self.tlm.duration_jobs(Some(tids));
+        if jobs.is_empty() {
+            return;
+        }
+        let jobs = match &self.duration_scan {
+            Some(scan) => match scan.add(jobs) {
+                Ok(()) => return,
+                Err(jobs) => jobs, // it has finished
+            },
+            None => jobs,
+        };
+        self.duration_scan =
+            Some(duration_scan::Scan::new(jobs, saved, self.sender));
+    }
+
+    pub(crate) fn on_durations_scanned(&mut self) {
+        if let Some(scan) = &self.duration_scan {
+            let durations: Vec<(TrackID, f64)> =
+                scan.receiver.try_iter().collect();
+            if !durations.is_empty() {
+                self.tlm.set_durations(&durations);
+                self.update_ui();
+            }
+        }
+    }
+}
+
+// Returns the folders the user chooses to search (if any)
+fn choose_folders_to_search() -> Vec<PathBuf> {
+    let mut folders = vec![];
+    loop {
+        let mut form = FileDialog::new(FileDialogType::BrowseDir);
+        form.set_title(&format!("Search Folder — {APPNAME}"));
+        let _ = form.set_directory(&util::get_track_dir(&PathBuf::new()));
+        form.show();
+        let folder = form.filename();
+        if !folder.is_dir() {
+            break;
+        }
+        folders.push(folder);
+        dialog::message_title(&format!("Check Tracks — {APPNAME}"));
+        if dialog::choice2_default(
+            "Search another folder as well?",
+            "&No",
+            "&Add Folder…",
+            "",
+        ) != Some(1)
+        {
+            break;
+        }
+    }
+    folders
 }
@@ -2,16 +2,21 @@
 // License: GPLv3
 
 use super::CONFIG;
-use crate::fixed::APPNAME;
-use crate::model::TreePath;
+use crate::fixed::{APPNAME, AUDIO_SUFFIXES, PATH_SEP};
+use crate::model::{Track, TreePath};
 use fltk::{app, dialog, tree::TreeItem};
-use lofty::{self, Accessor, ItemKey, ItemValue, Probe};
+use lofty::{self, Accessor, ItemKey, ItemValue, Probe, Tag};
 use std::{
     cmp,
     collections::VecDeque,
     path::{Path, PathBuf},
     str,
 };
+use tlmlib::playlists::Entry;
+pub use tlmlib::util::{
+    canonicalize, humanized_time, natural_cmp, rewrite_paths,
+    rewritten_path, track_name, treepath_escaped, treepath_names,
+};
 
 pub fn x() -> i32 {
     (app::screen_size().0 / 2.0) as i32
@@ -40,8 +45,10 @@ where
     }
 }
 
-pub fn isclose64(a: f64, b: f64) -> bool {
-    (a..=(a + f64::EPSILON)).contains(&b)
+// Durations are saved to 3 decimal places, so ones that are the same when
+// saved are the same
+pub fn same_secs(a: f64, b: f64) -> bool {
+    (a - b).abs() < 0.0005
 }
 
 pub fn isclose32(a: f32, b: f32) -> bool {
@@ -96,50 +103,6 @@ pub fn get_track_dir(track: &Path) -> PathBuf {
     PathBuf::from(".")
 }
 
-pub fn humanized_time(secs: f64) -> String {
-    const HR_SIGN: char = 'h';
-    const MIN_SIGN: char = '′';
-    const SEC_SIGN: char = '″';
-    if secs <= 0.0 {
-        return format!("0{SEC_SIGN}");
-    }
-    let hrs = (secs / 3600.0).floor();
-    let mut secs = secs % 3600.0;
-    let mut mins = (secs / 60.0).floor();
-    secs %= 60.0;
-    let mut hours = format!("{hrs:.0}");
-    let mut minutes = format!("{mins:.0}");
-    if minutes == "60" {
-        hours = format!("{:.0}", hrs + 1.0);
-        mins = 0.0;
-    }
-    let mut seconds = format!("{secs:.0}");
-    if seconds == "60" {
-        minutes = format!("{:.0}", mins + 1.0);
-        seconds.clear();
-    }
-    if hours == "0" || hours.is_empty() {
-        hours.clear();
-    } else {
-        hours.push(HR_SIGN);
-    }
-    if minutes == "0" || minutes.is_empty() {
-        minutes.clear();
-    } else {
-        minutes.push(MIN_SIGN);
-    }
-    if seconds == "0" || seconds.is_empty() {
-        seconds.clear();
-    } else {
-        seconds.push(SEC_SIGN);
-    }
-    if hours.is_empty() && minutes.is_empty() && seconds.is_empty() {
-        format!("0{SEC_SIGN}")
-    } else {
-        format!("{hours}{minutes}{seconds}")
-    }
-}
-
 pub fn get_track_data_html(track: &Path) -> String {
     let name = if let Some(name) = track.file_stem() {
         name.to_string_lossy()
@@ -205,15 +168,51 @@ pub fn get_track_data_html(track: &Path) -> String {
     }
 }
 
+// Returns the track with its tag data (if any) for writing to a playlist;
+// a virtual track is written as its whole file
+pub fn playlist_entry(track: &Track) -> Entry {
+    let secs = if track.is_virtual() {
+        get_track_secs(&track.filename).unwrap_or_default()
+    } else {
+        track.secs
+    };
+    let mut entry = Entry {
+        filename: track.filename.clone(),
+        secs,
+        ..Default::default()
+    };
+    if let Ok(Some(data)) = get_track_tag(&track.filename) {
+        entry.title = data.title;
+        entry.artist = data.artist;
+        entry.album = data.album;
+    }
+    entry
+}
+
+// Returns true if the path has one of the audio suffixes TLM can play
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension().is_some_and(|suffix| {
+        AUDIO_SUFFIXES
+            .iter()
+            .any(|audio_suffix| suffix.eq_ignore_ascii_case(audio_suffix))
+    })
+}
+
+pub fn get_track_secs(track: &Path) -> lofty::Result<f64> {
+    let tagged_file = Probe::open(track)?.guess_file_type()?.read(true)?;
+    Ok(tagged_file.properties().duration().as_secs_f64())
+}
+
 pub struct TrackData {
     pub title: String,
     pub album: String,
     pub artist: String,
     pub number: i32,
+    pub disc: i32,
     pub year: i32,
 }
 
-fn get_track_tag(track: &Path) -> lofty::Result<Option<TrackData>> {
+pub fn get_track_tag(track: &Path) -> lofty::Result<Option<TrackData>> {
     let tags = Probe::open(track)?.guess_file_type()?.read(false)?;
     if let Some(tag) = tags.primary_tag() {
         Ok(Some(TrackData {
@@ -232,19 +231,8 @@ fn get_track_tag(track: &Path) -> lofty::Result<Option<TrackData>> {
             } else {
                 String::new()
             },
-            number: if let Some(num_item) =
-                tag.get_item_ref(&ItemKey::TrackNumber)
-            {
-                match num_item.value() {
-                    ItemValue::Text(text) => match text.parse::<i32>() {
-                        Ok(n) => n,
-                        _ => 0,
-                    },
-                    _ => 0,
-                }
-            } else {
-                0
-            },
+            number: get_number(tag, &ItemKey::TrackNumber),
+            disc: get_number(tag, &ItemKey::DiscNumber),
             year: {
                 if let Some(date) = tag.get_string(&ItemKey::RecordingDate)
                 {
@@ -268,6 +256,19 @@ fn get_track_tag(track: &Path) -> lofty::Result<Option<TrackData>> {
     }
 }
 
+// Returns the tag's number for the key (e.g., 3 for a track number of "3"
+// or "3/12") or 0 if it has none
+fn get_number(tag: &Tag, key: &ItemKey) -> i32 {
+    match tag.get_item_ref(key).map(|item| item.value()) {
+        Some(ItemValue::Text(text)) => text
+            .split('/')
+            .next()
+            .and_then(|n| n.trim().parse::<i32>().ok())
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
 fn get_year_from_date(date: &str) -> i32 {
     if date.len() >= 4 {
         match date[..4].parse::<i32>() {
@@ -279,11 +280,13 @@ fn get_year_from_date(date: &str) -> i32 {
     }
 }
 
+// Names may contain any characters except control characters (such as
+// tabs) since they are escaped in tree paths and .tlm files
 pub fn sanitize(name: &str, default: &str) -> String {
     let s = name
         .trim_end_matches(&['-', '_', ' ', '>'])
         .trim_start_matches(&['-', '_', ' ', '<'])
-        .replace(&['/', '\\'], " ")
+        .replace(|c: char| c.is_control(), " ")
         .split_whitespace()
         .collect::<Vec<_>>()
         .join(" ");
@@ -294,24 +297,8 @@ pub fn sanitize(name: &str, default: &str) -> String {
     }
 }
 
-// Returns a name suitable as the last component of a tree path
-pub fn canonicalize(track: &Path) -> String {
-    let mut s = String::new();
-    if let Some(stem) = track.file_stem() {
-        s = stem.to_string_lossy().to_string();
-    }
-    s = s.trim_end_matches(&['-', '_', ' ', '>']).to_string();
-    let t = &s.trim_start_matches(&[
-        '-', '_', ' ', '<', '0', '1', '2', '3', '4', '5', '6', '7', '8',
-        '9',
-    ]);
-    if !t.is_empty() {
-        s = t.to_string();
-    }
-    s.replace(&['-', '_', '/', '\\'], " ")
-        .split_whitespace()
-        .collect::<Vec<_>>()
-        .join(" ")
+pub fn html_escaped(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
 }
 
 pub fn popup_error_message(message: &str) {
@@ -327,7 +314,7 @@ pub fn treepath_for_item(item: Option<TreeItem>) -> TreePath {
         if item.depth() > 0 {
             if let Some(name) = item.label() {
                 treepath.insert(0, '/');
-                treepath.insert_str(0, &name);
+                treepath.insert_str(0, &treepath_escaped(&name));
             }
         }
     }
@@ -338,6 +325,12 @@ pub fn treepath_for_item(item: Option<TreeItem>) -> TreePath {
     }
 }
 
+// Returns the tree path's names joined for display, e.g., AC/DC→Back in
+// Black
+pub fn treepath_display(treepath: &str) -> String {
+    treepath_names(treepath).join(PATH_SEP)
+}
+
 pub fn maybe_add_to_deque<T: cmp::PartialEq>(
     deque: &mut VecDeque<T>,
     value: T,
@@ -0,0 +1,206 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use anyhow::{bail, Result};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+use tlmlib::{
+    playlists, util, Item, List, Tlm, TrackPaths, FORMAT_VERSION,
+};
+
+pub fn tree(infile: &Path, paths: &TrackPaths) -> Result<i32> {
+    let tlm = load(infile, paths)?;
+    print_list(&tlm, &tlm.root, 0);
+    Ok(0)
+}
+
+fn print_list(tlm: &Tlm, list: &List, depth: usize) {
+    let pad = "  ".repeat(depth);
+    for item in &list.items {
+        match item {
+            Item::List(sublist) => {
+                println!("{pad}{}", sublist.name);
+                print_list(tlm, sublist, depth + 1);
+            }
+            Item::Track(tid) => {
+                if let Some(track) = tlm.track_for_tid.get(tid) {
+                    println!(
+                        "{pad}{} ({})",
+                        util::track_name(track),
+                        util::humanized_time(track.secs)
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub fn tracks(infile: &Path, paths: &TrackPaths) -> Result<i32> {
+    let tlm = load(infile, paths)?;
+    for tid in tlm.root.tids(true) {
+        if let Some(track) = tlm.track_for_tid.get(&tid) {
+            println!("{}\t{:.3}", track.filename.display(), track.secs);
+        }
+    }
+    Ok(0)
+}
+
+pub fn history(infile: &Path, paths: &TrackPaths) -> Result<i32> {
+    let tlm = load(infile, paths)?;
+    for treepath in &tlm.history {
+        println!("{treepath}");
+    }
+    Ok(0)
+}
+
+pub fn stats(infile: &Path, paths: &TrackPaths) -> Result<i32> {
+    let tlm = load(infile, paths)?;
+    let tids = tlm.root.tids(true);
+    let mut filenames = HashSet::new();
+    let mut secs = 0.0;
+    let mut missing = 0;
+    for tid in &tids {
+        if let Some(track) = tlm.track_for_tid.get(tid) {
+            if filenames.insert(&track.filename) && !track.filename.exists()
+            {
+                missing += 1;
+            }
+            secs += track.secs;
+        }
+    }
+    println!("lists:         {}", count_lists(&tlm.root));
+    println!("tracks:        {}", tids.len());
+    println!("unique tracks: {}", filenames.len());
+    println!("missing files: {missing}");
+    println!("duration:      {}", util::humanized_time(secs));
+    println!("history:       {}", tlm.history.len());
+    Ok(0)
+}
+
+fn count_lists(list: &List) -> usize {
+    list.items
+        .iter()
+        .map(|item| match item {
+            Item::List(sublist) => 1 + count_lists(sublist),
+            Item::Track(_) => 0,
+        })
+        .sum()
+}
+
+pub fn check(infile: &Path, paths: &TrackPaths) -> Result<i32> {
+    let tlm = load(infile, paths)?;
+    let mut code = 0;
+    for tid in tlm.root.tids(true) {
+        if let Some(track) = tlm.track_for_tid.get(&tid) {
+            if !track.filename.exists() {
+                println!("missing: {}", track.filename.display());
+                code = 1;
+            }
+        }
+    }
+    Ok(code)
+}
+
+pub fn convert(
+    infile: &Path,
+    outfile: &Path,
+    uncompressed: bool,
+    paths: &TrackPaths,
+) -> Result<i32> {
+    check_different(infile, outfile)?;
+    let tlm = load(infile, paths)?;
+    save(&tlm, outfile, uncompressed)?;
+    println!("saved {}", outfile.display());
+    Ok(0)
+}
+
+pub fn import(
+    infile: &Path,
+    outfile: &Path,
+    paths: &TrackPaths,
+) -> Result<i32> {
+    let (mut tlm, skipped) = if infile.is_dir() {
+        playlists::read_playlist_folder(infile)?
+    } else {
+        let mut tlm = Tlm::default();
+        let mut list = List::new(&util::canonicalize(infile));
+        let (tracks, skipped) = playlists::read_playlist(infile)?;
+        for track in tracks {
+            list.push_track(tlm.add_track(track));
+        }
+        tlm.root.push_list(list);
+        (tlm, skipped)
+    };
+    for what in &skipped {
+        eprintln!("warning: skipped {what}");
+    }
+    tlm.paths = paths.clone();
+    save(&tlm, outfile, false)?;
+    println!("saved {}", outfile.display());
+    Ok(0)
+}
+
+pub fn rewrite(
+    from: &Path,
+    to: &Path,
+    infile: &Path,
+    outfile: &Path,
+    uncompressed: bool,
+    paths: &TrackPaths,
+) -> Result<i32> {
+    check_different(infile, outfile)?;
+    let mut tlm = load(infile, paths)?;
+    let count = tlm.rewrite_paths(from, to);
+    save(&tlm, outfile, uncompressed)?;
+    println!("rewrote {count} track paths; saved {}", outfile.display());
+    Ok(0)
+}
+
+// The original is never overwritten so that it is still there if the
+// result isn't what was wanted
+fn check_different(infile: &Path, outfile: &Path) -> Result<()> {
+    if outfile.exists()
+        && fs::canonicalize(infile)? == fs::canonicalize(outfile)?
+    {
+        bail!("infile and outfile must be different");
+    }
+    Ok(())
+}
+
+// Newer files are read best-effort so this warns that some data may have
+// been skipped; the paths' roots and remaps are used for reading and for
+// saving
+fn load(infile: &Path, paths: &TrackPaths) -> Result<Tlm> {
+    let tlm = Tlm::load_with(infile, paths.clone())?;
+    if tlm.is_newer() {
+        eprintln!(
+            "warning: {} is .tlm version {} but tlmc only understands up \
+            to version {FORMAT_VERSION} so some of it may have been skipped",
+            infile.display(),
+            tlm.version
+        );
+    }
+    Ok(tlm)
+}
+
+fn save(tlm: &Tlm, outfile: &Path, uncompressed: bool) -> Result<()> {
+    if tlm.is_newer() {
+        bail!(
+            "won't save .tlm version {} as version {FORMAT_VERSION} since \
+            that could lose data",
+            tlm.version
+        );
+    }
+    if uncompressed {
+        let mut out = BufWriter::new(File::create(outfile)?);
+        tlm.write(&mut out, outfile)?;
+        out.flush()?;
+        Ok(())
+    } else {
+        tlm.save(outfile)
+    }
+}
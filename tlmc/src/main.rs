@@ -0,0 +1,149 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+mod commands;
+
+use anyhow::{bail, Result};
+use std::{env, ffi::OsString, fs, path::PathBuf, process};
+use tlmlib::TrackPaths;
+
+static USAGE: &str = "usage: tlmc <command> [options] [--] <file(s)>
+
+tree infile.tlm
+    show infile's lists and tracks as an indented tree
+tracks infile.tlm
+    show every track's filename and duration in tree order
+history infile.tlm
+    show infile's history of tree paths
+stats infile.tlm
+    show how many lists and tracks infile has and their total duration
+check infile.tlm
+    show any tracks whose files are missing (exit code 1 if any are)
+convert [-u|--uncompressed] infile.tlm outfile.tlm
+    load infile and save it as outfile (compressed unless -u is given);
+    outfile must not be infile
+import playlist.{cue,m3u,m3u8,pls,xspf}|folder outfile.tlm
+    save the playlist's tracks, or every .m3u(8) playlist in the folder
+    and its subfolders, as outfile
+rewrite [-u|--uncompressed] from-folder to-folder infile.tlm outfile.tlm
+    load infile, replace the from-folder prefix of every track path that
+    has it with to-folder, and save the result as outfile (compressed
+    unless -u is given); outfile must not be infile
+
+Every command also accepts these options, each of which may be repeated:
+--root NAME=DIR
+    read $NAME/ track paths as being in DIR, and save paths in DIR as
+    $NAME/ (like the GUI's Music Roots)
+--remap SHARED=LOCAL
+    read track paths in SHARED as being in LOCAL, and save paths in LOCAL
+    as in SHARED (like the GUI's Path Remaps)
+
+Use -- before any filenames that begin with -";
+
+fn main() {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    match run(&args) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(2);
+        }
+    }
+}
+
+// Returns the process's exit code
+fn run(args: &[OsString]) -> Result<i32> {
+    let command = match args.first().and_then(|arg| arg.to_str()) {
+        Some("-h" | "--help" | "help") | None => {
+            println!("{USAGE}");
+            return Ok(0);
+        }
+        Some(command) => command,
+    };
+    if !matches!(
+        command,
+        "tree"
+            | "tracks"
+            | "history"
+            | "stats"
+            | "check"
+            | "convert"
+            | "import"
+            | "rewrite"
+    ) {
+        bail!("unrecognized command: {command}\n\n{USAGE}");
+    }
+    let mut uncompressed = false;
+    let mut paths = TrackPaths::default();
+    let mut filenames = vec![];
+    let mut options_ended = false;
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        if options_ended || !arg.to_string_lossy().starts_with('-') {
+            filenames.push(PathBuf::from(arg));
+        } else if arg == "--" {
+            options_ended = true;
+        } else if (arg == "-u" || arg == "--uncompressed")
+            && matches!(command, "convert" | "rewrite")
+        {
+            uncompressed = true;
+        } else if arg == "--root" {
+            let (name, root) = option_pair(arg, args.next())?;
+            if !TrackPaths::is_root_name(&name) {
+                bail!("invalid --root name (use letters, digits, and _): {name}");
+            }
+            let root = PathBuf::from(root);
+            let root = fs::canonicalize(&root).unwrap_or(root);
+            paths.roots.push((name, root));
+        } else if arg == "--remap" {
+            let (shared, local) = option_pair(arg, args.next())?;
+            paths
+                .remaps
+                .push((PathBuf::from(shared), PathBuf::from(local)));
+        } else {
+            bail!(
+                "unrecognized option for {command}: {}\n\n{USAGE}",
+                arg.to_string_lossy()
+            );
+        }
+    }
+    match (command, &filenames[..]) {
+        ("tree", [infile]) => commands::tree(infile, &paths),
+        ("tracks", [infile]) => commands::tracks(infile, &paths),
+        ("history", [infile]) => commands::history(infile, &paths),
+        ("stats", [infile]) => commands::stats(infile, &paths),
+        ("check", [infile]) => commands::check(infile, &paths),
+        ("convert", [infile, outfile]) => {
+            commands::convert(infile, outfile, uncompressed, &paths)
+        }
+        ("import", [infile, outfile]) => {
+            commands::import(infile, outfile, &paths)
+        }
+        ("rewrite", [from, to, infile, outfile]) => commands::rewrite(
+            from,
+            to,
+            infile,
+            outfile,
+            uncompressed,
+            &paths,
+        ),
+        _ => bail!("wrong number of files for {command}\n\n{USAGE}"),
+    }
+}
+
+// Returns the option's NAME=VALUE argument split at the first =
+fn option_pair(
+    option: &OsString,
+    value: Option<&OsString>,
+) -> Result<(String, String)> {
+    let value = value.map(|value| value.to_string_lossy());
+    match value.as_deref().and_then(|value| value.split_once('=')) {
+        Some((name, value)) if !name.is_empty() && !value.is_empty() => {
+            Ok((name.to_string(), value.to_string()))
+        }
+        _ => bail!(
+            "{} needs a NAME=VALUE argument\n\n{USAGE}",
+            option.to_string_lossy()
+        ),
+    }
+}
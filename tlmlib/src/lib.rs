@@ -0,0 +1,28 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+//! Reads and writes Track List Manager `.tlm` files without needing a GUI.
+//!
+//! A `.tlm` file holds a tree of lists (each of which may contain tracks
+//! and other lists), the tracks themselves, and a history of recently
+//! played tracks' tree paths.
+
+mod list;
+mod paths;
+pub mod playlists;
+mod tlm;
+mod track;
+pub mod util;
+
+pub use crate::list::{Item, List};
+pub use crate::paths::TrackPaths;
+pub use crate::tlm::{
+    Damaged, Repair, Tlm, FORMAT_VERSION, PORTABLE_PATHS_VERSION,
+};
+pub use crate::track::Track;
+use std::collections::HashMap;
+
+pub type TreePath = String;
+pub type TrackID = i32;
+pub type TrackForTID = HashMap<TrackID, Track>;
+pub const INVALID_TID: TrackID = -1;
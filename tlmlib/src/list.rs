@@ -0,0 +1,64 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::TrackID;
+
+#[derive(Clone, Debug)]
+pub enum Item {
+    List(List),
+    Track(TrackID),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct List {
+    pub name: String,
+    pub items: Vec<Item>,
+}
+
+impl List {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), items: vec![] }
+    }
+
+    pub fn push_list(&mut self, list: List) {
+        self.items.push(Item::List(list));
+    }
+
+    pub fn push_track(&mut self, tid: TrackID) {
+        self.items.push(Item::Track(tid));
+    }
+
+    // Returns the child list with the given name, first appending a new
+    // empty one if there isn't one already
+    pub fn sublist_or_new(&mut self, name: &str) -> &mut List {
+        let index = match self.items.iter().position(
+            |item| matches!(item, Item::List(list) if list.name == name),
+        ) {
+            Some(index) => index,
+            None => {
+                self.push_list(List::new(name));
+                self.items.len() - 1
+            }
+        };
+        match &mut self.items[index] {
+            Item::List(list) => list,
+            Item::Track(_) => unreachable!(),
+        }
+    }
+
+    // Returns the TIDs of this list's tracks (and optionally of all its
+    // sublists' tracks) in tree order
+    pub fn tids(&self, recursive: bool) -> Vec<TrackID> {
+        let mut tids = vec![];
+        for item in &self.items {
+            match item {
+                Item::Track(tid) => tids.push(*tid),
+                Item::List(list) if recursive => {
+                    tids.extend(list.tids(recursive))
+                }
+                Item::List(_) => (),
+            }
+        }
+        tids
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::util;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+// How track paths are stored in .tlm files so that a .tlm file still
+// works when it and its music are moved or opened on another machine.
+// A track inside a named music root (e.g., MUSIC → /home/mark/Music) is
+// stored as $MUSIC/Queen/a.mp3; otherwise, if relative is true and the
+// track shares a folder with the .tlm file, it is stored relative to the
+// .tlm file's folder (e.g., ../Queen/a.mp3); otherwise it is stored as is.
+// Each remap is a (shared, local) pair of folder prefixes, e.g.,
+// (/home/a/Music, /mnt/nas/music): a stored path with the shared prefix
+// is read with the local prefix instead, and saved with the shared one
+// (unless it is in a music root, which takes precedence), so a .tlm
+// file can be shared between machines with different mounts
+#[derive(Clone, Debug)]
+pub struct TrackPaths {
+    pub relative: bool,
+    pub roots: Vec<(String, PathBuf)>,
+    pub remaps: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Default for TrackPaths {
+    fn default() -> Self {
+        Self { relative: true, roots: vec![], remaps: vec![] }
+    }
+}
+
+impl TrackPaths {
+    // Music root names may only have ASCII letters, digits, and
+    // underscores so that a $NAME/ prefix is unambiguous
+    pub fn is_root_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    // Returns the path to store for the track in a .tlm file that is in
+    // the given folder
+    pub fn stored(&self, path: &Path, folder: &Path) -> String {
+        encoded(&self.stored_path(path, folder))
+    }
+
+    fn stored_path(&self, path: &Path, folder: &Path) -> PathBuf {
+        if path.is_relative() {
+            return path.to_path_buf(); // e.g., an unresolved $ROOT path
+        }
+        // Prefer the deepest matching root; remaps only apply to tracks
+        // outside every root so that rooted tracks stay portable
+        let best = self
+            .roots
+            .iter()
+            .filter_map(|(name, root)| {
+                path.strip_prefix(root).ok().map(|rest| (name, rest))
+            })
+            .min_by_key(|(_, rest)| rest.components().count());
+        if let Some((name, rest)) = best {
+            let mut rooted =
+                OsString::from(format!("{ROOT_PREFIX}{name}/"));
+            rooted.push(slashed(rest));
+            return PathBuf::from(rooted);
+        }
+        if let Some(shared) = self.remapped(path, true) {
+            return shared;
+        }
+        if self.relative {
+            if let Some(relative) = util::relative_path(path, folder) {
+                let relative = slashed(&relative);
+                return if relative
+                    .to_string_lossy()
+                    .starts_with(ROOT_PREFIX)
+                {
+                    Path::new(".").join(relative) // so it isn't a root
+                } else {
+                    PathBuf::from(relative)
+                };
+            }
+        }
+        path.to_path_buf()
+    }
+
+    // Returns the path of a track as read (and decoded) from a .tlm file
+    // that is in the given folder; unknown roots are left as is
+    pub fn resolved(&self, stored: &Path, folder: &Path) -> PathBuf {
+        let mut components = stored.components();
+        if let Some(Component::Normal(first)) = components.next() {
+            if let Some(name) =
+                first.to_str().and_then(|s| s.strip_prefix(ROOT_PREFIX))
+            {
+                return match self.roots.iter().find(|(n, _)| n == name) {
+                    Some((_, root)) => {
+                        util::normalized(&root.join(components.as_path()))
+                    }
+                    None => stored.to_path_buf(),
+                };
+            }
+        }
+        if stored.is_relative() {
+            util::normalized(&folder.join(stored))
+        } else {
+            self.remapped(stored, false)
+                .unwrap_or_else(|| stored.to_path_buf())
+        }
+    }
+
+    // Returns the path with the (deepest) matching remap applied: local to
+    // shared for saving, shared to local for loading
+    fn remapped(&self, path: &Path, saving: bool) -> Option<PathBuf> {
+        self.remaps
+            .iter()
+            .map(
+                |(shared, local)| {
+                    if saving {
+                        (local, shared)
+                    } else {
+                        (shared, local)
+                    }
+                },
+            )
+            .filter(|(from, _)| path.starts_with(from))
+            .max_by_key(|(from, _)| from.components().count())
+            .and_then(|(from, to)| util::rewritten_path(path, from, to))
+    }
+}
+
+// Returns the absolute folder the .tlm file is (or will be) in
+pub fn folder_of(filename: &Path) -> PathBuf {
+    let folder = match filename.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    fs::canonicalize(folder).unwrap_or_else(|_| folder.to_path_buf())
+}
+
+// Stored paths always use / so that they work on every platform
+fn slashed(path: &Path) -> OsString {
+    let mut slashed = OsString::new();
+    for (i, component) in path.components().enumerate() {
+        if i > 0 {
+            slashed.push("/");
+        }
+        slashed.push(component.as_os_str());
+    }
+    slashed
+}
+
+// Returns the path as text for a .tlm file. Paths that aren't UTF-8 (or
+// that contain control characters such as tabs) are stored losslessly
+// with a leading ESCAPED character followed by the path's bytes, with
+// each %, control, and non-ASCII byte written as %XX
+pub fn encoded(path: &Path) -> String {
+    match path.to_str() {
+        Some(text) if !text.chars().any(|c| c.is_control()) => {
+            text.to_string()
+        }
+        _ => escaped(&path_bytes(path)),
+    }
+}
+
+// Returns the path from its text in a .tlm file (see encoded())
+pub fn decoded(text: &str) -> PathBuf {
+    match text.strip_prefix(ESCAPED) {
+        Some(text) => path_from_bytes(unescaped(text.as_bytes())),
+        None => PathBuf::from(text),
+    }
+}
+
+// Returns the bytes as ESCAPED followed by the bytes with each %, control,
+// and non-ASCII byte written as %XX
+pub(crate) fn escaped(bytes: &[u8]) -> String {
+    let mut text = String::from(ESCAPED);
+    for &byte in bytes {
+        if byte == b'%' || byte.is_ascii_control() || !byte.is_ascii() {
+            text.push_str(&format!("%{byte:02X}"));
+        } else {
+            text.push(byte as char);
+        }
+    }
+    text
+}
+
+// Returns the bytes from escaped() text (without its leading ESCAPED), or
+// from any other %XX escaped text, e.g., a file: URI's path
+pub(crate) fn unescaped(escaped: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        let byte = if escaped[i] == b'%' {
+            std::str::from_utf8(escaped.get(i + 1..i + 3).unwrap_or(b""))
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match byte {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(escaped[i]);
+                i += 1;
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+// Other platforms' non-Unicode paths can't be stored losslessly
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+pub(crate) fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+const ROOT_PREFIX: &str = "$";
+pub(crate) const ESCAPED: char = '\x0E';
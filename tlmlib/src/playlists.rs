@@ -0,0 +1,746 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::{paths, util, Tlm, Track, TrackID};
+use anyhow::{bail, Result};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use walkdir::WalkDir;
+
+// A track as written to a playlist; the tag data may be empty
+#[derive(Clone, Debug, Default)]
+pub struct Entry {
+    pub filename: PathBuf,
+    pub secs: f64,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+// Reads the playlist's tracks using the reader for its file suffix; also
+// returns a note of each entry that was skipped (e.g., a missing track)
+pub fn read_playlist(filename: &Path) -> Result<(Vec<Track>, Vec<String>)> {
+    match filename.extension().and_then(|suffix| suffix.to_str()) {
+        Some(suffix)
+            if suffix.eq_ignore_ascii_case("m3u")
+                || suffix.eq_ignore_ascii_case("m3u8") =>
+        {
+            read_m3u(filename)
+        }
+        Some(suffix) if suffix.eq_ignore_ascii_case("pls") => {
+            read_pls(filename)
+        }
+        Some(suffix) if suffix.eq_ignore_ascii_case("xspf") => {
+            read_xspf(filename)
+        }
+        Some(suffix) if suffix.eq_ignore_ascii_case("cue") => {
+            read_cue(filename)
+        }
+        _ => bail!(
+            "can only read .m3u, .m3u8, .pls, .xspf, or .cue playlists"
+        ),
+    }
+}
+
+// Writes the entries using the writer for the playlist's file suffix
+pub fn write_playlist(
+    filename: &Path,
+    entries: &[Entry],
+    relative: bool,
+) -> Result<()> {
+    match filename.extension().and_then(|suffix| suffix.to_str()) {
+        Some(suffix)
+            if suffix.eq_ignore_ascii_case("m3u")
+                || suffix.eq_ignore_ascii_case("m3u8") =>
+        {
+            write_m3u(filename, entries, relative)
+        }
+        Some(suffix) if suffix.eq_ignore_ascii_case("pls") => {
+            write_pls(filename, entries, relative)
+        }
+        Some(suffix) if suffix.eq_ignore_ascii_case("xspf") => {
+            write_xspf(filename, entries, relative)
+        }
+        _ => bail!("can only write .m3u, .m3u8, .pls, or .xspf playlists"),
+    }
+}
+
+/*
+BNF:
+    M3U      ::= '#EXTM3U'? (DIRECTIVE | ENTRY)*
+    ENTRY    ::= INFO? LOCATION
+    INFO     ::= '#EXTINF:' SECONDS (' ' ATTRIBUTES)? ',' TITLE
+    SECONDS  ::= -?\d+(\.\d+)?
+    TITLE    ::= .*
+    LOCATION ::= FILENAME | URI
+    DIRECTIVE ::= '#' .* # comments and other #EXT directives are ignored
+
+Example:
+    #EXTM3U
+
+    #EXTINF:-1,You and I
+    /home/mark/music/Queen/05-You_and_I.mp3
+    Queen/06-Death_on_Two_Legs.mp3
+    file:///home/mark/music/Queen/07-Lazing%20on%20a%20Sunday.mp3
+
+This reads both .m3u and .m3u8 playlists, including plain lists of
+filenames. Relative filenames are relative to the playlist; file: URIs are
+percent-decoded and other URIs (e.g., http:) are skipped. Text that isn't
+UTF-8 is read as Latin-1, as used by many older .m3u files; its filenames
+are then the bytes as they are in the playlist (or, if there's no such
+file, their UTF-8 equivalent).
+*/
+
+pub fn read_m3u(filename: &Path) -> Result<(Vec<Track>, Vec<String>)> {
+    static EXTINF: &str = "#EXTINF:";
+    let folder = playlist_folder(filename);
+    let (text, latin1) = read_text(filename)?;
+    let mut secs = 0.0;
+    let mut tracks = vec![];
+    let mut skipped = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue; // ignore blank lines
+        }
+        if let Some(info) = line.strip_prefix(EXTINF) {
+            let seconds =
+                info.split(&[',', ' ']).next().unwrap_or_default();
+            secs = f64::from_str(seconds).unwrap_or(0.0).max(0.0);
+        } else if line.starts_with('#') {
+            continue; // ignore #EXTM3U, other directives, and comments
+        } else {
+            match location_path(&folder, line, latin1) {
+                Some(filename) if filename.exists() => {
+                    tracks.push(Track::new(filename, secs))
+                }
+                Some(filename) => skipped
+                    .push(format!("missing track: {}", filename.display())),
+                None => skipped.push(format!("non-file track: {line}")),
+            }
+            secs = 0.0;
+        }
+    }
+    Ok((tracks, skipped))
+}
+
+// Writes the entries as an extended M3U8 playlist with absolute paths or
+// with paths relative to the playlist's folder
+pub fn write_m3u(
+    filename: &Path,
+    entries: &[Entry],
+    relative: bool,
+) -> Result<()> {
+    let folder = playlist_folder(filename);
+    let mut out = BufWriter::new(File::create(filename)?);
+    out.write_all(b"#EXTM3U\n")?;
+    for entry in entries {
+        out.write_all(
+            format!(
+                "\n#EXTINF:{},{}\n{}\n",
+                entry_secs(entry),
+                entry_title(entry),
+                entry_path(&folder, &entry.filename, relative).display()
+            )
+            .as_bytes(),
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Returns the entry's whole seconds or -1 if its duration is unknown
+fn entry_secs(entry: &Entry) -> i64 {
+    if entry.secs > 0.0 {
+        entry.secs.round() as i64
+    } else {
+        -1
+    }
+}
+
+// Returns "artist - title", or the title, or if the entry has no tag data,
+// a title based on its filename; always on one line
+fn entry_title(entry: &Entry) -> String {
+    let title = if entry.title.is_empty() {
+        util::canonicalize(&entry.filename)
+    } else if entry.artist.is_empty() {
+        entry.title.clone()
+    } else {
+        format!("{} - {}", entry.artist, entry.title)
+    };
+    title.replace(['\r', '\n'], " ")
+}
+
+// Returns the track's path as it should be written to a playlist in the
+// given folder
+fn entry_path(folder: &Path, filename: &Path, relative: bool) -> PathBuf {
+    if relative {
+        util::relative_path(filename, folder)
+            .unwrap_or_else(|| filename.to_path_buf())
+    } else {
+        filename.to_path_buf()
+    }
+}
+
+// Returns the file's text (without any byte order mark) read as UTF-8 or,
+// failing that, as Latin-1, and whether it was read as Latin-1
+fn read_text(filename: &Path) -> Result<(String, bool)> {
+    let mut bytes = fs::read(filename)?;
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        bytes.drain(..3);
+    }
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => (text, false),
+        Err(err) => {
+            (err.into_bytes().iter().map(|&b| b as char).collect(), true)
+        }
+    })
+}
+
+/*
+BNF:
+    PLS     ::= '[playlist]' ENTRY+ ('NumberOfEntries=' \d+)? ('Version=2')?
+    ENTRY   ::= FILE TITLE? LENGTH?
+    FILE    ::= 'File' N '=' FILENAME
+    TITLE   ::= 'Title' N '=' .+
+    LENGTH  ::= 'Length' N '=' -?\d+
+    N       ::= \d+
+
+Example:
+    [playlist]
+    File1=Queen/05-You_and_I.mp3
+    Title1=You and I
+    Length1=207
+    NumberOfEntries=1
+    Version=2
+
+Entries are ordered by N; titles are ignored since tracks get their names
+from their filenames. The text is read as for M3U (UTF-8 or Latin-1) and
+malformed lines are skipped. Filenames are read as for M3U: relative filenames
+are relative to the playlist, file: URIs are percent-decoded, and other
+URIs (e.g., http:) are skipped.
+*/
+
+pub fn read_pls(filename: &Path) -> Result<(Vec<Track>, Vec<String>)> {
+    let folder = playlist_folder(filename);
+    let mut entries = BTreeMap::<usize, (String, f64)>::new();
+    let mut skipped = vec![];
+    let (text, latin1) = read_text(filename)?;
+    for (lino, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['[', ';', '#']) {
+            continue; // ignore blank lines, the header, and comments
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                skipped
+                    .push(format!("malformed line {}: {line}", lino + 1));
+                continue;
+            }
+        };
+        let (name, n) = key.split_at(
+            key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len()),
+        );
+        let n = match n.parse::<usize>() {
+            Ok(n) => n,
+            _ => continue, // NumberOfEntries, Version, or unknown
+        };
+        let entry = entries.entry(n).or_insert((String::new(), 0.0));
+        if name.eq_ignore_ascii_case("file") {
+            entry.0 = value.to_string();
+        } else if name.eq_ignore_ascii_case("length") {
+            entry.1 = f64::from_str(value).unwrap_or(0.0).max(0.0);
+        }
+    }
+    let mut tracks = vec![];
+    for (location, secs) in entries.into_values() {
+        if location.is_empty() {
+            continue; // title or length without a file
+        }
+        match location_path(&folder, &location, latin1) {
+            Some(filename) if filename.exists() => {
+                tracks.push(Track::new(filename, secs))
+            }
+            Some(filename) => skipped
+                .push(format!("missing track: {}", filename.display())),
+            None => skipped.push(format!("non-file track: {location}")),
+        }
+    }
+    Ok((tracks, skipped))
+}
+
+// Writes the entries as a version 2 PLS playlist with absolute paths or
+// with paths relative to the playlist's folder
+pub fn write_pls(
+    filename: &Path,
+    entries: &[Entry],
+    relative: bool,
+) -> Result<()> {
+    let folder = playlist_folder(filename);
+    let mut out = BufWriter::new(File::create(filename)?);
+    out.write_all(b"[playlist]\n")?;
+    for (i, entry) in entries.iter().enumerate() {
+        let n = i + 1;
+        out.write_all(
+            format!(
+                "\nFile{n}={}\nTitle{n}={}\nLength{n}={}\n",
+                entry_path(&folder, &entry.filename, relative).display(),
+                entry_title(entry),
+                entry_secs(entry)
+            )
+            .as_bytes(),
+        )?;
+    }
+    out.write_all(
+        format!("\nNumberOfEntries={}\nVersion=2\n", entries.len())
+            .as_bytes(),
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/*
+Example:
+    <?xml version="1.0" encoding="UTF-8"?>
+    <playlist version="1" xmlns="http://xspf.org/ns/0/">
+      <trackList>
+        <track>
+          <location>file:///home/mark/music/Queen/05-You%20and%20I.mp3</location>
+          <title>You and I</title>
+          <duration>207000</duration>
+        </track>
+      </trackList>
+    </playlist>
+
+Every <track> in a <trackList> is read, including those in nested
+<trackList>s, in document order. The first <location> that is a file URI
+(or a plain path) is used, with relative locations being relative to the
+playlist. <duration> is in milliseconds.
+*/
+
+pub fn read_xspf(filename: &Path) -> Result<(Vec<Track>, Vec<String>)> {
+    let folder = playlist_folder(filename);
+    let text = fs::read_to_string(filename)?;
+    let doc = roxmltree::Document::parse(&text)?;
+    let mut tracks = vec![];
+    let mut skipped = vec![];
+    for node in doc.descendants().filter(|node| {
+        is_element(node, "track")
+            && node.parent().is_some_and(|p| is_element(&p, "trackList"))
+    }) {
+        let mut track = None;
+        let mut secs = 0.0;
+        for child in node.children() {
+            if is_element(&child, "location") && track.is_none() {
+                track = child.text().and_then(|location| {
+                    uri_path(&folder, location.as_bytes())
+                });
+            } else if is_element(&child, "duration") {
+                secs = child
+                    .text()
+                    .and_then(|ms| f64::from_str(ms.trim()).ok())
+                    .map_or(0.0, |ms| (ms / 1000.0).max(0.0));
+            }
+        }
+        match track {
+            Some(filename) if filename.exists() => {
+                tracks.push(Track::new(filename, secs))
+            }
+            Some(filename) => skipped
+                .push(format!("missing track: {}", filename.display())),
+            None => (), // no location or a non-file URI
+        }
+    }
+    Ok((tracks, skipped))
+}
+
+// Writes the entries as an XSPF playlist with file: URIs or with URI
+// references relative to the playlist's folder; tag data is only written
+// if present
+pub fn write_xspf(
+    filename: &Path,
+    entries: &[Entry],
+    relative: bool,
+) -> Result<()> {
+    let folder = playlist_folder(filename);
+    let mut out = BufWriter::new(File::create(filename)?);
+    out.write_all(
+        b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n\
+        <trackList>\n",
+    )?;
+    for entry in entries {
+        let path = entry_path(&folder, &entry.filename, relative);
+        let mut text = format!(
+            "<track>\n<location>{}</location>\n",
+            xml_escaped(&location_uri(&path, relative))
+        );
+        for (name, value) in [
+            ("title", &entry.title),
+            ("creator", &entry.artist),
+            ("album", &entry.album),
+        ] {
+            if !value.is_empty() {
+                text.push_str(&format!(
+                    "<{name}>{}</{name}>\n",
+                    xml_escaped(value)
+                ));
+            }
+        }
+        if entry.secs > 0.0 {
+            text.push_str(&format!(
+                "<duration>{}</duration>\n",
+                (entry.secs * 1000.0).round() as i64
+            ));
+        }
+        text.push_str("</track>\n");
+        out.write_all(text.as_bytes())?;
+    }
+    out.write_all(b"</trackList>\n</playlist>\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+// Returns the path as a file: URI or, if relative, as a relative URI
+// reference
+fn location_uri(path: &Path, relative: bool) -> String {
+    let text = path.to_string_lossy().to_string();
+    #[cfg(windows)]
+    let text = text.replace('\\', "/");
+    if relative {
+        return percent_encoded(&text);
+    }
+    #[cfg(windows)]
+    if let [drive, b':', ..] = text.as_bytes() {
+        return format!(
+            "file:///{}:{}",
+            *drive as char,
+            percent_encoded(&text[2..])
+        );
+    }
+    format!("file://{}", percent_encoded(&text))
+}
+
+// Returns the text with every byte other than '/' and URI unreserved
+// characters replaced by its %XX escape
+fn percent_encoded(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+// Control characters other than tab and newlines aren't allowed in XML
+// (even escaped) so they are dropped
+fn xml_escaped(text: &str) -> String {
+    text.chars()
+        .filter(|&c| c >= ' ' || matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_element(node: &roxmltree::Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+// Returns the path for a playlist entry that is a filename or a URI (see
+// uri_path()), or None for URIs that aren't files
+fn location_path(
+    folder: &Path,
+    location: &str,
+    latin1: bool,
+) -> Option<PathBuf> {
+    if location.contains("://") {
+        uri_path(folder, &entry_bytes(location, latin1))
+    } else {
+        Some(file_path(folder, location, latin1))
+    }
+}
+
+// Returns the path for a playlist entry that is a filename. A Latin-1
+// playlist's filename is the bytes it was read from, unless there's no
+// such file but there is one whose name is the filename's UTF-8
+fn file_path(folder: &Path, entry: &str, latin1: bool) -> PathBuf {
+    let path = track_path(
+        folder,
+        paths::path_from_bytes(entry_bytes(entry, latin1)),
+    );
+    if latin1 && !path.exists() {
+        let utf8 = track_path(folder, PathBuf::from(entry));
+        if utf8.exists() {
+            return utf8;
+        }
+    }
+    path
+}
+
+// Returns the entry's bytes as they were in the playlist (see read_text())
+fn entry_bytes(entry: &str, latin1: bool) -> Vec<u8> {
+    if latin1 {
+        entry.chars().map(|c| c as u8).collect()
+    } else {
+        entry.as_bytes().to_vec()
+    }
+}
+
+// Returns the path for a file URI or relative URI reference (resolving
+// relative paths against the playlist's folder), or None for other URIs,
+// e.g., http:
+fn uri_path(folder: &Path, uri: &[u8]) -> Option<PathBuf> {
+    let uri = uri.trim_ascii();
+    if let Some(path) = uri.strip_prefix(b"file://") {
+        let path = path.strip_prefix(b"localhost").unwrap_or(path);
+        let path = paths::unescaped(path);
+        if path.is_empty() {
+            return None;
+        }
+        #[cfg(windows)]
+        let path = match path.as_slice() {
+            [b'/', _, b':', ..] => path[1..].to_vec(), // /C:/...
+            _ => path,
+        };
+        Some(track_path(folder, paths::path_from_bytes(path)))
+    } else if uri.windows(3).any(|scheme| scheme == b"://") {
+        None
+    } else {
+        Some(track_path(
+            folder,
+            paths::path_from_bytes(paths::unescaped(uri)),
+        ))
+    }
+}
+
+/*
+Example:
+    PERFORMER "Queen"
+    TITLE "A Night at the Opera"
+    FILE "A Night at the Opera.flac" WAVE
+      TRACK 01 AUDIO
+        TITLE "Death on Two Legs"
+        INDEX 01 00:00:00
+      TRACK 02 AUDIO
+        TITLE "Lazing on a Sunday Afternoon"
+        INDEX 00 03:42:50
+        INDEX 01 03:43:00
+
+Each AUDIO TRACK becomes a virtual track named by its TITLE, starting at
+its INDEX 01 (or, if it has none, at its first INDEX) and ending where the
+next track in the same FILE starts (or at the end of the file for the
+last one). Times are mm:ss:ff with 75 frames per second. Tracks without
+an INDEX are skipped. Other commands (e.g., PERFORMER, REM, PREGAP) are
+ignored.
+*/
+
+pub fn read_cue(filename: &Path) -> Result<(Vec<Track>, Vec<String>)> {
+    let folder = playlist_folder(filename);
+    let mut starts = Vec::<(PathBuf, Option<f64>, String)>::new();
+    let mut file = None;
+    let mut in_audio_track = false;
+    let (text, latin1) = read_text(filename)?;
+    for (lino, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let (command, rest) =
+            line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        if command.eq_ignore_ascii_case("FILE") {
+            // The filename may be quoted and is followed by the file type
+            let name = match rest.strip_prefix('"') {
+                Some(rest) => rest.rsplit_once('"').map(|(name, _)| name),
+                None => rest.rsplit_once(' ').map(|(name, _)| name),
+            };
+            match name {
+                Some(name) => file = Some(file_path(&folder, name, latin1)),
+                None => bail!("{}:invalid FILE: {line}", lino + 1),
+            }
+            in_audio_track = false;
+        } else if command.eq_ignore_ascii_case("TRACK") {
+            let file = match &file {
+                Some(file) => file,
+                None => bail!("{}:TRACK before FILE", lino + 1),
+            };
+            in_audio_track = rest.to_ascii_uppercase().ends_with("AUDIO");
+            if in_audio_track {
+                starts.push((file.clone(), None, String::new()));
+            }
+        } else if command.eq_ignore_ascii_case("TITLE") && in_audio_track {
+            if let Some(start) = starts.last_mut() {
+                start.2 = rest.trim_matches('"').to_string();
+            }
+        } else if command.eq_ignore_ascii_case("INDEX") && in_audio_track {
+            let (number, time) =
+                rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            match (
+                number.parse::<u32>(),
+                cue_time(time.trim()),
+                starts.last_mut(),
+            ) {
+                // INDEX 01 is the start; any other is only used if the
+                // track has no INDEX 01
+                (Ok(number), Some(secs), Some(start))
+                    if number == 1 || start.1.is_none() =>
+                {
+                    start.1 = Some(secs)
+                }
+                (Ok(_), Some(_), Some(_)) => {}
+                _ => bail!("{}:invalid INDEX: {line}", lino + 1),
+            }
+        }
+    }
+    let mut tracks = vec![];
+    let mut skipped = vec![];
+    for (i, (filename, start, title)) in starts.iter().enumerate() {
+        let start = match start {
+            Some(start) => *start,
+            None => {
+                skipped.push(format!(
+                    "track {} without an INDEX: {}",
+                    i + 1,
+                    filename.display()
+                ));
+                continue;
+            }
+        };
+        // A track ends where the next one in its file that has an INDEX
+        // starts
+        let end = starts[i + 1..]
+            .iter()
+            .take_while(|(next_filename, ..)| next_filename == filename)
+            .find_map(|(_, next_start, _)| *next_start)
+            .unwrap_or(0.0); // end of file
+        if filename.exists() {
+            let mut track =
+                Track::new_virtual(filename.clone(), start, end);
+            track.title = title.clone();
+            tracks.push(track);
+        } else {
+            skipped.push(format!("missing track: {}", filename.display()));
+        }
+    }
+    Ok((tracks, skipped))
+}
+
+// Returns the seconds for a CUE mm:ss:ff time
+fn cue_time(time: &str) -> Option<f64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(mins)), Some(Some(secs)), Some(Some(frames)), None) => {
+            Some(mins as f64 * 60.0 + secs as f64 + frames as f64 / 75.0)
+        }
+        _ => None,
+    }
+}
+
+// Returns the absolute path of the folder containing the playlist
+fn playlist_folder(filename: &Path) -> PathBuf {
+    let folder = filename.parent().unwrap_or_else(|| Path::new(""));
+    fs::canonicalize(folder).unwrap_or_else(|_| folder.to_path_buf())
+}
+
+// Returns the playlist entry's path, resolving relative paths against the
+// playlist's folder
+fn track_path(folder: &Path, path: PathBuf) -> PathBuf {
+    if path.is_relative() {
+        util::normalized(&folder.join(path))
+    } else {
+        path
+    }
+}
+
+/*
+Reads every .m3u (or .m3u8) playlist in the folder and its subfolders.
+Playlists in a folder called "playlists" become top-level lists; those in
+any other folder become sublists of a top-level list named after their
+folder. Subfolders and playlists that can't be read are skipped, and are
+returned with the playlists' skipped entries.
+*/
+pub fn read_playlist_folder(folder: &Path) -> Result<(Tlm, Vec<String>)> {
+    static PLAYLISTS: &str = "playlists";
+    let mut tlm = Tlm::default();
+    let mut skipped = vec![];
+    for entry in WalkDir::new(folder).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) if err.depth() == 0 => bail!(err), // the folder itself
+            Err(err) => {
+                skipped.push(err.to_string());
+                continue;
+            }
+        };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let folder_name = entry.file_name().to_string_lossy().to_string();
+        let playlists = match m3u_files(entry.path()) {
+            Ok(playlists) => playlists,
+            Err(err) => {
+                skipped.push(format!("{}: {err}", entry.path().display()));
+                continue;
+            }
+        };
+        for playlist in playlists {
+            let name = match playlist.file_stem() {
+                Some(stem) => stem.to_string_lossy().to_string(),
+                None => continue,
+            };
+            let tracks = match read_m3u(&playlist) {
+                Ok((tracks, missing)) => {
+                    skipped.extend(missing.iter().map(|what| {
+                        format!("{}: {what}", playlist.display())
+                    }));
+                    tracks
+                }
+                Err(err) => {
+                    skipped.push(format!("{}: {err}", playlist.display()));
+                    continue;
+                }
+            };
+            let tids: Vec<TrackID> = tracks
+                .into_iter()
+                .map(|track| tlm.add_track(track))
+                .collect();
+            let parent = if folder_name == PLAYLISTS {
+                &mut tlm.root
+            } else {
+                tlm.root.sublist_or_new(&folder_name)
+            };
+            let list = parent.sublist_or_new(&name);
+            for tid in tids {
+                list.push_track(tid);
+            }
+        }
+    }
+    Ok((tlm, skipped))
+}
+
+// Returns the folder's .m3u and .m3u8 files sorted case-insensitively
+fn m3u_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut playlists = vec![];
+    for entry in fs::read_dir(folder)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_file() {
+            if let Some(suffix) = path.extension() {
+                if suffix.eq_ignore_ascii_case("m3u")
+                    || suffix.eq_ignore_ascii_case("m3u8")
+                {
+                    playlists.push(path);
+                }
+            }
+        }
+    }
+    playlists.sort_by_key(|path| path.to_string_lossy().to_uppercase());
+    Ok(playlists)
+}
@@ -0,0 +1,731 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::paths::{self, TrackPaths};
+use crate::util;
+use crate::{Item, List, Track, TrackForTID, TrackID, TreePath};
+use anyhow::{bail, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::prelude::*,
+    io::BufReader,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Debug)]
+pub struct Tlm {
+    pub filename: PathBuf,
+    pub root: List,
+    pub track_for_tid: TrackForTID,
+    pub next_tid: TrackID,
+    pub history: VecDeque<TreePath>,
+    pub paths: TrackPaths,
+    // The format version of the file it was loaded from; it is always
+    // saved as FORMAT_VERSION
+    pub version: u32,
+}
+
+impl Default for Tlm {
+    fn default() -> Self {
+        Self {
+            filename: PathBuf::new(),
+            root: List::default(),
+            track_for_tid: TrackForTID::default(),
+            next_tid: 1,
+            history: VecDeque::default(),
+            paths: TrackPaths::default(),
+            version: FORMAT_VERSION,
+        }
+    }
+}
+
+impl Tlm {
+    pub fn load(filename: &Path) -> Result<Self> {
+        Self::load_with(filename, TrackPaths::default())
+    }
+
+    // Loads the file resolving its relative and root-based track paths
+    pub fn load_with(filename: &Path, paths: TrackPaths) -> Result<Self> {
+        Ok(Self::read(filename, paths, false)?.0)
+    }
+
+    // Loads as much of a damaged file as it can, skipping or repairing
+    // malformed lines, and returns what it skipped or repaired in line
+    // order; it only fails if the file can't be read at all
+    pub fn load_lenient(
+        filename: &Path,
+        paths: TrackPaths,
+    ) -> Result<(Self, Vec<Repair>)> {
+        Self::read(filename, paths, true)
+    }
+
+    fn read(
+        filename: &Path,
+        paths: TrackPaths,
+        lenient: bool,
+    ) -> Result<(Self, Vec<Repair>)> {
+        let mut repairs = Repairs { lenient, repairs: vec![] };
+        let text = get_text(filename, &mut repairs)?;
+        let mut tlm = Self::parse_text(&text, &mut repairs)?;
+        tlm.filename = filename.to_path_buf();
+        let folder = paths::folder_of(filename);
+        for track in tlm.track_for_tid.values_mut() {
+            if tlm.version < PORTABLE_PATHS_VERSION
+                && track.filename.is_relative()
+            {
+                // Older files had no roots, e.g., $X/a.mp3 is just
+                // relative, and like every relative path it is now read as
+                // relative to the .tlm file's folder (see the upgrade note
+                // the GUI shows for such files)
+                track.filename = Path::new(".").join(&track.filename);
+            }
+            track.filename = paths.resolved(&track.filename, &folder);
+        }
+        tlm.paths = paths;
+        repairs.repairs.sort_by_key(|repair| repair.lino);
+        Ok((tlm, repairs.repairs))
+    }
+
+    // Files older than FORMAT_VERSION are upgraded as they are read;
+    // newer files are read best-effort, i.e., sections and track fields
+    // this version doesn't know about are skipped (see is_newer())
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::parse_text(
+            text,
+            &mut Repairs { lenient: false, repairs: vec![] },
+        )
+    }
+
+    fn parse_text(text: &str, repairs: &mut Repairs) -> Result<Self> {
+        let mut tlm = Self::default();
+        // stack[0] is the root; the last is the list currently being read
+        let mut stack = vec![List::default()];
+        let mut state = State::WantMagic;
+        for (i, line) in text.lines().enumerate() {
+            let lino = i + 1;
+            if line.is_empty() {
+                continue;
+            }
+            if state == State::WantMagic {
+                tlm.version = read_version(lino, line, repairs)?;
+                state = State::WantTrackHeader;
+                continue;
+            }
+            if line.starts_with(SECTION) {
+                state = tlm.read_section(lino, line, state, repairs)?;
+                continue;
+            }
+            if state == State::WantTrackHeader {
+                repairs.fix(
+                    lino,
+                    "",
+                    "missing TRACKS",
+                    "added missing TRACKS",
+                )?;
+                state = State::InTracks;
+            }
+            match state {
+                State::InTracks => {
+                    if line.starts_with(INDENT) {
+                        let escaped = tlm.has_escaped_names();
+                        read_list(&mut stack, lino, line, escaped, repairs);
+                    } else if let Some(tid) =
+                        tlm.read_track(lino, line, repairs)?
+                    {
+                        if let Some(list) = stack.last_mut() {
+                            list.push_track(tid);
+                        }
+                    }
+                }
+                State::InHistory => {
+                    tlm.history.push_back(if tlm.has_escaped_names() {
+                        unescaped_name(line)
+                    } else {
+                        line.to_string()
+                    });
+                }
+                // Only newer files' unknown sections are skipped silently
+                State::Skipping if !tlm.is_newer() => repairs.fix(
+                    lino,
+                    line,
+                    "unknown section",
+                    "skipped line of unknown section",
+                )?,
+                _ => {}
+            }
+        }
+        close_lists(&mut stack, 0);
+        if let Some(root) = stack.pop() {
+            tlm.root = root;
+        }
+        Ok(tlm)
+    }
+
+    // Returns true if the file was saved by a newer version (so may have
+    // had data that was skipped and would be lost if it was saved)
+    pub fn is_newer(&self) -> bool {
+        self.version > FORMAT_VERSION
+    }
+
+    fn has_escaped_names(&self) -> bool {
+        self.version >= ESCAPED_NAMES_VERSION
+    }
+
+    // Returns the state for the section that starts at the line; unknown
+    // sections are only allowed (and skipped) in newer files
+    fn read_section(
+        &self,
+        lino: usize,
+        line: &str,
+        state: State,
+        repairs: &mut Repairs,
+    ) -> Result<State> {
+        Ok(match (line, state) {
+            (TRACKS, State::WantTrackHeader | State::Skipping) => {
+                State::InTracks
+            }
+            (HISTORY, State::InTracks | State::Skipping) => {
+                State::InHistory
+            }
+            _ if self.is_newer() => State::Skipping,
+            (TRACKS | HISTORY, _) => {
+                repairs.fix(
+                    lino,
+                    line,
+                    "misplaced section",
+                    "read misplaced section",
+                )?;
+                if line == TRACKS {
+                    State::InTracks
+                } else {
+                    State::InHistory
+                }
+            }
+            _ => {
+                repairs.fix(
+                    lino,
+                    line,
+                    "unknown section",
+                    "skipped unknown section",
+                )?;
+                State::Skipping
+            }
+        })
+    }
+
+    // Returns the new track's TID or None if the line had to be skipped
+    fn read_track(
+        &mut self,
+        lino: usize,
+        line: &str,
+        repairs: &mut Repairs,
+    ) -> Result<Option<TrackID>> {
+        let mut parts = line.split(TAB);
+        let (filename, secs) = match (parts.next(), parts.next()) {
+            (Some(filename), Some(secs)) if !filename.is_empty() => {
+                (filename, secs)
+            }
+            _ => {
+                repairs.fix(
+                    lino,
+                    line,
+                    "failed to read track",
+                    "skipped unreadable track",
+                )?;
+                return Ok(None);
+            }
+        };
+        let mut track = Track::new(
+            paths::decoded(filename),
+            read_secs(lino, line, secs, repairs),
+        );
+        // Virtual tracks also have start and end offsets and may have a
+        // title; any further fields are from a newer version
+        if let (Some(start), Some(end)) = (parts.next(), parts.next()) {
+            track.start = read_secs(lino, line, start, repairs);
+            track.end = read_secs(lino, line, end, repairs);
+            if self.version >= TRACK_TITLES_VERSION {
+                if let Some(title) = parts.next() {
+                    track.title = unescaped_name(title);
+                }
+            }
+        }
+        Ok(Some(self.add_track(track)))
+    }
+
+    // Adds the track to the track_for_tid map (but not to any list) and
+    // returns its new TID
+    pub fn add_track(&mut self, track: Track) -> TrackID {
+        let tid = self.next_tid;
+        self.track_for_tid.insert(tid, track);
+        self.next_tid += 1;
+        tid
+    }
+
+    // Adds the other's lists, tracks, and history that this doesn't have,
+    // matching lists by name and tracks by file (and offsets) within their
+    // list; nothing is removed; returns how many lists and tracks were
+    // added
+    pub fn merge(&mut self, other: &Tlm) -> usize {
+        let mut root = std::mem::take(&mut self.root);
+        let added = self.merge_list(&mut root, &other.root, other);
+        self.root = root;
+        for treepath in &other.history {
+            if !self.history.contains(treepath) {
+                self.history.push_back(treepath.clone());
+            }
+        }
+        added
+    }
+
+    fn merge_list(
+        &mut self,
+        list: &mut List,
+        from: &List,
+        other: &Tlm,
+    ) -> usize {
+        let mut added = 0;
+        for item in &from.items {
+            match item {
+                Item::List(sublist) => {
+                    let count = list.items.len();
+                    let mine = list.sublist_or_new(&sublist.name);
+                    added += self.merge_list(mine, sublist, other);
+                    if list.items.len() > count {
+                        added += 1;
+                    }
+                }
+                Item::Track(tid) => {
+                    if let Some(track) = other.track_for_tid.get(tid) {
+                        if !self.has_track(list, track) {
+                            // Tracks must precede sublists in .tlm files
+                            let index = list
+                                .items
+                                .iter()
+                                .position(|item| {
+                                    matches!(item, Item::List(_))
+                                })
+                                .unwrap_or(list.items.len());
+                            let tid = self.add_track(track.clone());
+                            list.items.insert(index, Item::Track(tid));
+                            added += 1;
+                        }
+                    }
+                }
+            }
+        }
+        added
+    }
+
+    fn has_track(&self, list: &List, track: &Track) -> bool {
+        list.tids(false).iter().any(|tid| {
+            self.track_for_tid.get(tid).is_some_and(|mine| {
+                mine.filename == track.filename
+                    && mine.start == track.start
+                    && mine.end == track.end
+            })
+        })
+    }
+
+    // Replaces the from prefix of every track path that has it with to;
+    // returns how many paths were rewritten
+    pub fn rewrite_paths(&mut self, from: &Path, to: &Path) -> usize {
+        util::rewrite_paths(&mut self.track_for_tid, from, to)
+    }
+
+    // Saves to a temporary file in the same folder which then replaces
+    // the file, so a failed save (e.g., because the disk is full) never
+    // damages the existing file
+    pub fn save(&self, filename: &Path) -> Result<()> {
+        // Replace a symlink's target rather than the symlink itself
+        let target =
+            fs::canonicalize(filename).unwrap_or_else(|_| filename.into());
+        let temp = temp_filename(&target);
+        let result = self
+            .save_to(&temp, filename, &target)
+            .and_then(|_| Ok(fs::rename(&temp, &target)?));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp); // Ignore error
+        }
+        result
+    }
+
+    fn save_to(
+        &self,
+        temp: &Path,
+        filename: &Path,
+        target: &Path,
+    ) -> Result<()> {
+        let file = File::create(temp)?;
+        if let Ok(metadata) = fs::metadata(target) {
+            let _ = fs::set_permissions(temp, metadata.permissions());
+        }
+        let mut gz = GzEncoder::new(file, Compression::best());
+        self.write(&mut gz, filename)?;
+        gz.finish()?.sync_all()?;
+        Ok(())
+    }
+
+    // The filename is where the text is to be saved since track paths
+    // may be stored relative to it
+    pub fn write<W: Write>(
+        &self,
+        out: &mut W,
+        filename: &Path,
+    ) -> Result<()> {
+        out.write_all(
+            format!("{MAGIC}{FORMAT_VERSION}\n{TRACKS}\n").as_bytes(),
+        )?;
+        let folder = paths::folder_of(filename);
+        self.write_list(out, &self.root, 1, &folder)?;
+        out.write_all(format!("{HISTORY}\n").as_bytes())?;
+        for treepath in &self.history {
+            out.write_all(
+                format!("{}\n", escaped_name(treepath)).as_bytes(),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_list<W: Write>(
+        &self,
+        out: &mut W,
+        list: &List,
+        depth: usize,
+        folder: &Path,
+    ) -> Result<()> {
+        for item in &list.items {
+            match item {
+                Item::Track(tid) => {
+                    if let Some(track) = self.track_for_tid.get(tid) {
+                        let mut line = format!(
+                            "{}\t{:.3}",
+                            self.paths.stored(&track.filename, folder),
+                            track.secs
+                        );
+                        if track.is_virtual() || !track.title.is_empty() {
+                            line.push_str(&format!(
+                                "\t{:.3}\t{:.3}",
+                                track.start, track.end
+                            ));
+                            if !track.title.is_empty() {
+                                line.push('\t');
+                                line.push_str(&escaped_name(&track.title));
+                            }
+                        }
+                        line.push('\n');
+                        out.write_all(line.as_bytes())?;
+                    }
+                }
+                Item::List(sublist) => {
+                    let indent = INDENT.to_string().repeat(depth);
+                    out.write_all(
+                        format!(
+                            "{indent}{}\n",
+                            escaped_name(&sublist.name)
+                        )
+                        .as_bytes(),
+                    )?;
+                    self.write_list(out, sublist, depth + 1, folder)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// A line that a lenient load skipped or repaired; line is empty if the
+// problem was a missing line
+#[derive(Clone, Debug)]
+pub struct Repair {
+    pub lino: usize,
+    pub line: String,
+    pub what: String,
+}
+
+// The error for a .tlm file that is damaged, i.e., that has malformed
+// lines or can't be fully decompressed, as opposed to one that is missing,
+// unreadable, or not a .tlm file at all; only a damaged file is worth
+// loading with load_lenient()
+#[derive(Debug)]
+pub struct Damaged(pub String);
+
+impl fmt::Display for Damaged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Damaged {}
+
+// Records repairs; when not lenient, problems that need more than a
+// minor repair are errors instead
+struct Repairs {
+    lenient: bool,
+    repairs: Vec<Repair>,
+}
+
+impl Repairs {
+    // Fails with the error unless lenient, in which case the fix is
+    // recorded
+    fn fix(
+        &mut self,
+        lino: usize,
+        line: &str,
+        error: &str,
+        fixed: &str,
+    ) -> Result<()> {
+        if !self.lenient {
+            return Err(Damaged(if line.is_empty() {
+                format!("error:{lino}: {error}")
+            } else {
+                format!("error:{lino}: {error}: {line:?}")
+            })
+            .into());
+        }
+        self.repair(lino, line, fixed);
+        Ok(())
+    }
+
+    fn repair(&mut self, lino: usize, line: &str, what: &str) {
+        self.repairs.push(Repair {
+            lino,
+            line: line.to_string(),
+            what: what.to_string(),
+        });
+    }
+}
+
+// Returns the file's format version; a version older than any TLM wrote
+// is unsupported, while an unreadable one is damage, so it is assumed to
+// be FORMAT_VERSION when lenient
+fn read_version(
+    lino: usize,
+    line: &str,
+    repairs: &mut Repairs,
+) -> Result<u32> {
+    let version = match line.strip_prefix(MAGIC) {
+        Some(version) => version.trim(),
+        None => bail!("error:{lino}: not a .tlm file"),
+    };
+    match version.parse() {
+        Ok(version) if version >= OLDEST_VERSION => Ok(version),
+        Ok(_) => {
+            bail!("error:{lino}: unsupported .tlm version {version:?}")
+        }
+        Err(_) if repairs.lenient => {
+            repairs.repair(
+                lino,
+                line,
+                &format!("unsupported version; read as {FORMAT_VERSION}"),
+            );
+            Ok(FORMAT_VERSION)
+        }
+        Err(_) => Err(Damaged(format!(
+            "error:{lino}: unreadable .tlm version {version:?}"
+        ))
+        .into()),
+    }
+}
+
+// Returns the seconds (or 0 if they're invalid)
+fn read_secs(
+    lino: usize,
+    line: &str,
+    secs: &str,
+    repairs: &mut Repairs,
+) -> f64 {
+    match f64::from_str(secs) {
+        Ok(secs) if secs.is_finite() => secs.max(0.0),
+        _ => {
+            repairs.repair(lino, line, &format!("read {secs:?} as 0 secs"));
+            0.0
+        }
+    }
+}
+
+fn read_list(
+    stack: &mut Vec<List>,
+    lino: usize,
+    line: &str,
+    escaped: bool,
+    repairs: &mut Repairs,
+) {
+    let name = line.trim_start_matches(INDENT);
+    let indent = line.len() - name.len();
+    let name =
+        if escaped { unescaped_name(name) } else { name.to_string() };
+    // The new list's parent is at depth indent - 1, or is the current
+    // list if the indent skips levels
+    if indent > stack.len() {
+        repairs.repair(lino, line, "made a sublist of the list above");
+    }
+    let parent_depth = (indent - 1).min(stack.len() - 1);
+    close_lists(stack, parent_depth);
+    stack.push(List::new(&name));
+}
+
+// List names (and track titles and history tree paths) that contain
+// control characters such as the INDENT or TAB used by the format are
+// stored escaped just like such track paths
+fn escaped_name(name: &str) -> String {
+    if name.chars().any(|c| c.is_control()) {
+        paths::escaped(name.as_bytes())
+    } else {
+        name.to_string()
+    }
+}
+
+fn unescaped_name(text: &str) -> String {
+    match text.strip_prefix(paths::ESCAPED) {
+        Some(text) => {
+            String::from_utf8_lossy(&paths::unescaped(text.as_bytes()))
+                .to_string()
+        }
+        None => text.to_string(),
+    }
+}
+
+// Pops lists until the list at the given depth is last, adding each popped
+// list to its parent
+fn close_lists(stack: &mut Vec<List>, depth: usize) {
+    while stack.len() > depth + 1 {
+        if let Some(list) = stack.pop() {
+            if let Some(parent) = stack.last_mut() {
+                parent.push_list(list);
+            }
+        }
+    }
+}
+
+// When lenient, the text of a truncated or corrupt file up to where it
+// became unreadable is returned
+fn get_text(filename: &Path, repairs: &mut Repairs) -> Result<String> {
+    let compressed = is_compressed(filename)?;
+    let mut bytes = vec![];
+    let file = File::open(filename)?;
+    let read = if compressed {
+        let mut gz = GzDecoder::new(file);
+        gz.read_to_end(&mut bytes)
+    } else {
+        let mut buffer = BufReader::new(file);
+        buffer.read_to_end(&mut bytes)
+    };
+    if let Err(err) = read {
+        if !repairs.lenient {
+            return Err(if compressed {
+                Damaged(format!("failed to decompress: {err}")).into()
+            } else {
+                err.into()
+            });
+        }
+        // What was read is likely to end partway through a line (e.g., in
+        // the middle of a track's path) so that line is dropped
+        let complete = bytes
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |i| i + 1);
+        let lino =
+            bytes[..complete].iter().filter(|&&byte| byte == b'\n').count()
+                + 1;
+        let truncated = bytes.split_off(complete);
+        if !truncated.is_empty() {
+            repairs.repair(
+                lino,
+                &String::from_utf8_lossy(&truncated),
+                "discarded truncated last line",
+            );
+        }
+        repairs.repair(
+            lino,
+            "",
+            &format!("the rest of the file is unreadable: {err}"),
+        );
+    }
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(err) => escaped_text(err.as_bytes()),
+    })
+}
+
+// Files saved by older versions (or other tools) may have track paths
+// that aren't UTF-8, so these are converted to escaped paths; anything
+// else that isn't UTF-8 is replaced with U+FFFD
+fn escaped_text(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    let mut in_tracks = true;
+    for line in bytes.split(|&byte| byte == b'\n') {
+        if line.starts_with(b"\x0CHISTORY") {
+            in_tracks = false;
+        }
+        match std::str::from_utf8(line) {
+            Ok(line) => text.push_str(line),
+            Err(_)
+                if in_tracks
+                    && !line.starts_with(b"\x0B")
+                    && !line.starts_with(b"\x0C") =>
+            {
+                let end = line
+                    .iter()
+                    .position(|&byte| byte == TAB as u8)
+                    .unwrap_or(line.len());
+                let filename = paths::path_from_bytes(line[..end].to_vec());
+                text.push_str(&paths::encoded(&filename));
+                text.push_str(&String::from_utf8_lossy(&line[end..]));
+            }
+            Err(_) => text.push_str(&String::from_utf8_lossy(line)),
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// Returns a hidden file in the same folder, e.g., .music.tlm.tmp
+fn temp_filename(filename: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(filename.file_name().unwrap_or_default());
+    name.push(".tmp");
+    filename.with_file_name(name)
+}
+
+fn is_compressed(filename: &Path) -> Result<bool> {
+    let mut file = File::open(filename)?;
+    let mut buffer = [0; 2]; // 0x1F 0x8B gzip magic
+    file.read_exact(&mut buffer)?;
+    Ok(buffer[0] == 0x1F && buffer[1] == 0x8B)
+}
+
+const MAGIC: &str = "\x0CTLM\t";
+const SECTION: char = '\x0C';
+const TRACKS: &str = "\x0CTRACKS";
+const HISTORY: &str = "\x0CHISTORY";
+// 100 is the original format; 101 added virtual track start and end
+// offsets, which every version reads
+const OLDEST_VERSION: u32 = 100;
+// 102 added relative and root-based track paths
+pub const PORTABLE_PATHS_VERSION: u32 = 102;
+// 104 added escaped list names and history tree paths (103 added escaped
+// track paths; older files' raw non-UTF-8 paths are escaped by get_text())
+const ESCAPED_NAMES_VERSION: u32 = 104;
+// 105 added titles (e.g., from CUE sheets) after track offsets
+const TRACK_TITLES_VERSION: u32 = 105;
+// The version written; each version adds to the one before, and files are
+// upgraded as they are read by checking the version before reading what
+// was added
+pub const FORMAT_VERSION: u32 = TRACK_TITLES_VERSION;
+const INDENT: char = '\x0B';
+const TAB: char = '\x09';
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    WantMagic,
+    WantTrackHeader,
+    InTracks,
+    InHistory,
+    Skipping, // an unknown section of a newer file
+}
@@ -0,0 +1,31 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use std::path::PathBuf;
+
+// A virtual track (e.g., from a CUE sheet) is the part of its file from
+// start to end seconds; an end of 0 means the end of the file. A track's
+// name is its title or, if it has none, is made from its filename
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub filename: PathBuf,
+    pub secs: f64,
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+impl Track {
+    pub fn new(filename: PathBuf, secs: f64) -> Self {
+        Self { filename, secs, start: 0.0, end: 0.0, title: String::new() }
+    }
+
+    pub fn new_virtual(filename: PathBuf, start: f64, end: f64) -> Self {
+        let secs = if end > start { end - start } else { 0.0 };
+        Self { filename, secs, start, end, title: String::new() }
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        self.start > 0.0 || self.end > 0.0
+    }
+}
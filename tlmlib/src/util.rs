@@ -0,0 +1,242 @@
+// Copyright © 2022 Mark Summerfield. All rights reserved.
+// License: GPLv3
+
+use crate::{Track, TrackForTID};
+use std::{
+    cmp::Ordering,
+    iter::Peekable,
+    path::{Component, Path, PathBuf},
+    str::Chars,
+};
+
+pub fn humanized_time(secs: f64) -> String {
+    const HR_SIGN: char = 'h';
+    const MIN_SIGN: char = '′';
+    const SEC_SIGN: char = '″';
+    if secs <= 0.0 {
+        return format!("0{SEC_SIGN}");
+    }
+    let hrs = (secs / 3600.0).floor();
+    let mut secs = secs % 3600.0;
+    let mut mins = (secs / 60.0).floor();
+    secs %= 60.0;
+    let mut hours = format!("{hrs:.0}");
+    let mut minutes = format!("{mins:.0}");
+    if minutes == "60" {
+        hours = format!("{:.0}", hrs + 1.0);
+        mins = 0.0;
+    }
+    let mut seconds = format!("{secs:.0}");
+    if seconds == "60" {
+        minutes = format!("{:.0}", mins + 1.0);
+        seconds.clear();
+    }
+    if hours == "0" || hours.is_empty() {
+        hours.clear();
+    } else {
+        hours.push(HR_SIGN);
+    }
+    if minutes == "0" || minutes.is_empty() {
+        minutes.clear();
+    } else {
+        minutes.push(MIN_SIGN);
+    }
+    if seconds == "0" || seconds.is_empty() {
+        seconds.clear();
+    } else {
+        seconds.push(SEC_SIGN);
+    }
+    if hours.is_empty() && minutes.is_empty() && seconds.is_empty() {
+        format!("0{SEC_SIGN}")
+    } else {
+        format!("{hours}{minutes}{seconds}")
+    }
+}
+
+// Returns a name suitable as the last component of a tree path
+pub fn canonicalize(track: &Path) -> String {
+    let mut s = String::new();
+    if let Some(stem) = track.file_stem() {
+        s = stem.to_string_lossy().to_string();
+    }
+    s = s.trim_end_matches(['-', '_', ' ', '>']).to_string();
+    let t = &s.trim_start_matches([
+        '-', '_', ' ', '<', '0', '1', '2', '3', '4', '5', '6', '7', '8',
+        '9',
+    ]);
+    if !t.is_empty() {
+        s = t.to_string();
+    }
+    s.replace(['-', '_', '/', '\\'], " ")
+        .replace(|c: char| c.is_control(), " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Compares names so that numbers within them sort numerically (e.g.,
+// "2 Song" before "10 Song"); other characters are compared
+// case-insensitively
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (x.peek(), y.peek()) {
+            (None, None) => return a.cmp(b), // equal but for case
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d))
+                if c.is_ascii_digit() && d.is_ascii_digit() =>
+            {
+                let (m, n) = (digits(&mut x), digits(&mut y));
+                let (m, n) =
+                    (m.trim_start_matches('0'), n.trim_start_matches('0'));
+                m.len().cmp(&n.len()).then_with(|| m.cmp(n))
+            }
+            (Some(c), Some(d)) => {
+                let ordering = c.to_lowercase().cmp(d.to_lowercase());
+                x.next();
+                y.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+// Returns the track's name for use as the last component of a tree path;
+// virtual tracks also show where they start in their file
+pub fn track_name(track: &Track) -> String {
+    if !track.title.is_empty() {
+        return track.title.clone();
+    }
+    let name = canonicalize(&track.filename);
+    if track.is_virtual() {
+        format!("{name} @ {}", humanized_time(track.start))
+    } else {
+        name
+    }
+}
+
+// Returns the path relative to the base folder (e.g., ../Queen/a.mp3), or
+// None if they have no folder in common (not just the filesystem root,
+// e.g., if they're on different drives); both paths should be absolute
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    if path.is_relative() || base.is_relative() {
+        return None;
+    }
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if !path_components[..common]
+        .iter()
+        .any(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+// Returns the path with any . and .. components removed without touching
+// the filesystem (so symlinks are left alone); a relative path's leading
+// .. components are kept
+pub fn normalized(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normal.push(component),
+            },
+            _ => normal.push(component),
+        }
+    }
+    normal
+}
+
+// Replaces the from prefix of every track path that has it with to;
+// returns how many paths were rewritten
+pub fn rewrite_paths(
+    track_for_tid: &mut TrackForTID,
+    from: &Path,
+    to: &Path,
+) -> usize {
+    let mut count = 0;
+    for track in track_for_tid.values_mut() {
+        if let Some(path) = rewritten_path(&track.filename, from, to) {
+            track.filename = path;
+            count += 1;
+        }
+    }
+    count
+}
+
+// Returns the path with its from prefix replaced by to (e.g.,
+// /home/a/Music/Queen/a.mp3 → /mnt/nas/music/Queen/a.mp3), or None if
+// the path doesn't start with from
+pub fn rewritten_path(
+    path: &Path,
+    from: &Path,
+    to: &Path,
+) -> Option<PathBuf> {
+    let rest = path.strip_prefix(from).ok()?;
+    Some(if rest.as_os_str().is_empty() {
+        to.to_path_buf()
+    } else {
+        to.join(rest)
+    })
+}
+
+// Returns the name escaped for use as a tree path component: since tree
+// paths are joined with '/', a '/' or '\' in a name is escaped with '\'
+// (as FLTK's Tree expects), e.g., AC/DC → AC\/DC
+pub fn treepath_escaped(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c == '/' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Returns the tree path's (unescaped) names
+pub fn treepath_names(treepath: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut name = String::new();
+    let mut chars = treepath.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => name.push(chars.next().unwrap_or('\\')),
+            '/' => names.push(std::mem::take(&mut name)),
+            _ => name.push(c),
+        }
+    }
+    names.push(name);
+    names
+}